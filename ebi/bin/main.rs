@@ -1,12 +1,12 @@
 use ebi::sources::{archive::SourceArchiver, SourceManager};
 
 fn main() {
-    let mut sources = SourceManager::default();
+    let mut sources = SourceManager::builder().build().unwrap();
     let archive = SourceArchiver::from(&sources);
     sources.load_sources().unwrap();
 
     let manga = sources.manga_list("opex").unwrap();
-    let manga = manga.get(0).unwrap();
+    let manga = manga.first().unwrap();
     let manga = archive.save_manga_cover(manga).unwrap();
 
     let chapters = sources.chapter_list(&manga).unwrap();
@@ -0,0 +1,133 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ebi_source::locale::Locale;
+use serde::{Deserialize, Serialize};
+
+use crate::error::EbiError;
+use crate::sources::cache::CacheOperation;
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePolicy {
+    // Unsigned sources are loaded, signed ones must still be valid and trusted
+    #[default]
+    AllowUnsigned,
+    // Only sources signed by a trusted key are loaded
+    RequireSigned,
+}
+
+// Used for every request made by the host (downloads, repositories). Sources
+// make their own requests
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    // ureq's user agent if not set
+    pub user_agent: Option<String>,
+    // <protocol>://<user>:<password>@<host>:<port>, e.g.: "http://127.0.0.1:8080".
+    // http(s)://, socks4:// or socks5://
+    pub proxy: Option<String>,
+    // Seconds, no timeout if not set
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    // PEM certificates trusted in addition to the bundled roots
+    pub ca_bundle: Option<PathBuf>,
+    // Sent with the requests made for a source (covers, pages), by source identifier
+    pub source_headers: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+// Seconds a source response is served from the cache, 0 to always call the source
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub manga_list: u64,
+    pub search: u64,
+    pub chapter_list: u64,
+    pub chapter_page_list: u64,
+    // Seconds past its ttl a response is still served, while it is refreshed in the
+    // background. Older responses are only served when the source fails
+    pub stale_while_revalidate: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            manga_list: 60 * 60,
+            search: 10 * 60,
+            chapter_list: 30 * 60,
+            chapter_page_list: 10 * 60,
+            stale_while_revalidate: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn ttl(&self, operation: CacheOperation) -> Duration {
+        let ttl = match operation {
+            CacheOperation::MangaList => self.manga_list,
+            CacheOperation::Search => self.search,
+            CacheOperation::ChapterList => self.chapter_list,
+            CacheOperation::ChapterPageList => self.chapter_page_list,
+        };
+        Duration::from_secs(ttl)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EbiConfig {
+    // Sources copied to the source directory by hand
+    pub signature_policy: SignaturePolicy,
+    // Sources installed from a repository (the ones with an installed.json)
+    pub repository_signature_policy: SignaturePolicy,
+    // Base64 encoded minisign public keys
+    pub trusted_keys: Vec<String>,
+    pub http: HttpConfig,
+    // Preferred chapter languages, every language when empty
+    pub languages: Vec<Locale>,
+    pub cache: CacheConfig,
+}
+
+impl Default for EbiConfig {
+    fn default() -> Self {
+        Self {
+            signature_policy: SignaturePolicy::AllowUnsigned,
+            repository_signature_policy: SignaturePolicy::RequireSigned,
+            trusted_keys: Vec::new(),
+            http: HttpConfig::default(),
+            languages: Vec::new(),
+            cache: CacheConfig::default(),
+        }
+    }
+}
+
+impl EbiConfig {
+    // <dir>/config.json
+    pub fn path<P: AsRef<Path>>(dir: P) -> PathBuf {
+        let mut path = dir.as_ref().to_path_buf();
+        path.push(CONFIG_FILE_NAME);
+        path
+    }
+
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, EbiError> {
+        let path = Self::path(dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let config = std::fs::read(&path)?;
+        serde_json::from_slice(&config).map_err(|e| EbiError::InvalidConfig(e.to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<(), EbiError> {
+        let config =
+            serde_json::to_vec_pretty(self).map_err(|e| EbiError::InvalidConfig(e.to_string()))?;
+        std::fs::write(Self::path(dir), config)
+            .map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))
+    }
+}
@@ -1,11 +1,99 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use crate::error::EbiError;
+use crate::{config::HttpConfig, error::EbiError, sources::cookies::CookieStore};
+
+// Shared agent built from the HttpConfig. Cloning it keeps the same connection pool
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    agent: ureq::Agent,
+    source_headers: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            agent: ureq::Agent::new(),
+            source_headers: BTreeMap::new(),
+        }
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> Result<Self, EbiError> {
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(ref user_agent) = config.user_agent {
+            agent = agent.user_agent(user_agent);
+        }
+        if let Some(ref proxy) = config.proxy {
+            let proxy = ureq::Proxy::new(proxy)
+                .map_err(|e| EbiError::InvalidConfig(format!("proxy :: {}", e)))?;
+            agent = agent.proxy(proxy);
+        }
+        if let Some(timeout) = config.connect_timeout {
+            agent = agent.timeout_connect(Duration::from_secs(timeout));
+        }
+        if let Some(timeout) = config.read_timeout {
+            agent = agent.timeout_read(Duration::from_secs(timeout));
+        }
+        if let Some(ref ca_bundle) = config.ca_bundle {
+            agent = agent.tls_config(Arc::new(tls_config(ca_bundle)?));
+        }
+
+        Ok(Self {
+            agent: agent.build(),
+            source_headers: config.source_headers.clone(),
+        })
+    }
+
+    // With the headers configured for `source`
+    pub fn get(&self, url: &str, source: Option<&str>) -> ureq::Request {
+        let mut request = self.agent.get(url);
+        let headers = source.and_then(|source| self.source_headers.get(source));
+        for (name, value) in headers.into_iter().flatten() {
+            request = request.set(name, value);
+        }
+        request
+    }
+}
+
+// Bundled roots, plus every certificate of the PEM file
+fn tls_config(ca_bundle: &Path) -> Result<rustls::ClientConfig, EbiError> {
+    let invalid = |e: String| EbiError::InvalidConfig(format!("{} :: {}", ca_bundle.display(), e));
+    let pem = std::fs::read(ca_bundle).map_err(|e| invalid(e.to_string()))?;
+
+    let mut roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+
+    let mut certificates = 0;
+    for certificate in rustls_pemfile::certs(&mut pem.as_slice()) {
+        let certificate = certificate.map_err(|e| invalid(e.to_string()))?;
+        roots.add(certificate).map_err(|e| invalid(e.to_string()))?;
+        certificates += 1;
+    }
+    if certificates == 0 {
+        return Err(invalid("no certificate found".to_owned()));
+    }
+
+    // Same provider as ureq
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    Ok(rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| invalid(e.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
 
 pub fn http_download<P>(
-    url: &str,
+    mut request: ureq::Request,
     file_name: &str,
     target_dir: P,
+    cookies: Option<&CookieStore>,
 ) -> Result<KnownFileExtensions, EbiError>
 where
     P: AsRef<Path>,
@@ -16,7 +104,16 @@ where
     }
 
     let mut buffer = Vec::new();
-    let response = ureq::get(url).call()?;
+    let url = request.url().to_owned();
+    if let Some(header) = cookies.and_then(|cookies| cookies.cookie_header(&url)) {
+        request = request.set("Cookie", &header);
+    }
+    let response = request.call()?;
+    if let Some(cookies) = cookies {
+        for set_cookie in response.all("set-cookie") {
+            cookies.set_cookie(&url, set_cookie);
+        }
+    }
 
     let content_type = response
         .header("content-type")
@@ -34,12 +131,31 @@ where
     Ok(file_ext)
 }
 
+// Supports local (file://) urls, so repositories can be served from disk. Only indexes
+// read from disk may point to them, see RepositoryIndex::fetch
+pub fn fetch(http: &HttpClient, url: &str) -> Result<Vec<u8>, EbiError> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(std::fs::read(path)?);
+    }
+
+    let mut buffer = Vec::new();
+    http.get(url, None)
+        .call()?
+        .into_reader()
+        .read_to_end(&mut buffer)
+        .map_err(|_| EbiError::CouldNotReadBuffer)?;
+
+    Ok(buffer)
+}
+
 pub enum KnownFileExtensions {
     Jpeg,
     Png,
 }
 
 impl KnownFileExtensions {
+    pub const ALL: [Self; 2] = [Self::Jpeg, Self::Png];
+
     pub fn try_from_content_type(header: &str) -> Result<Self, EbiError> {
         match header {
             "image/jpeg" => Ok(Self::Jpeg),
@@ -61,3 +177,56 @@ impl std::fmt::Display for KnownFileExtensions {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Self-signed, valid until 2126
+    const CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBfjCCASOgAwIBAgIUPeyZsDvc7WEQ6pZm8oTZul1YLCQwCgYIKoZIzj0EAwIw
+EzERMA8GA1UEAwwIZWJpIHRlc3QwIBcNMjYxMDE5MDkyODU0WhgPMjEyNjA5MjUw
+OTI4NTRaMBMxETAPBgNVBAMMCGViaSB0ZXN0MFkwEwYHKoZIzj0CAQYIKoZIzj0D
+AQcDQgAEuU7MatutI3tknUjYphykdQtZjo/pVZHapjSenSEaGFGLaiEOiY2NxzEz
+//9M8/zU/Ns4Rvr2QhJzQ7LRXUPBDqNTMFEwHQYDVR0OBBYEFBzawv/Xm9WuPruj
+XVkqc3p6bPAsMB8GA1UdIwQYMBaAFBzawv/Xm9WuPrujXVkqc3p6bPAsMA8GA1Ud
+EwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSQAwRgIhAOoR1HW5/+UWY/Pt+yWfAFxW
+FDtRt8ErbLhWl7k6fmfUAiEAoaujXD3fFi8nJG3nXZ10eQB2GUqj8aFWpYNK1cwj
+ArE=
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn tls_config_adds_every_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.pem");
+        std::fs::write(&path, format!("# Comment\n{}{}", CERTIFICATE, CERTIFICATE)).unwrap();
+
+        assert!(tls_config(&path).is_ok());
+    }
+
+    #[test]
+    fn tls_config_requires_a_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.pem");
+
+        std::fs::write(&path, "no certificates").unwrap();
+        assert!(matches!(tls_config(&path), Err(EbiError::InvalidConfig(_))));
+
+        let truncated = CERTIFICATE.replace("ArE=\n", "");
+        std::fs::write(&path, truncated).unwrap();
+        assert!(matches!(tls_config(&path), Err(EbiError::InvalidConfig(_))));
+
+        assert!(tls_config(&dir.path().join("missing.pem")).is_err());
+    }
+
+    #[test]
+    fn socks_proxies_are_supported() {
+        let config = HttpConfig {
+            proxy: Some("socks5://127.0.0.1:1080".to_owned()),
+            ..Default::default()
+        };
+        assert!(HttpClient::new(&config).is_ok());
+    }
+}
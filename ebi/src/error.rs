@@ -7,17 +7,28 @@ pub enum EbiError {
     #[error("UNKNOWN_ERROR::{0}")]
     Unknown(String),
 
-    #[error("DUPLICATED_SOURCE")]
-    DuplicatedSource,
+    #[error("DUPLICATED_SOURCE::{0}")]
+    DuplicatedSource(String),
+    // Directory name, identifier returned by source_info
+    #[error("SOURCE_IDENTIFIER_MISMATCH::{0}::{1}")]
+    IdentifierMismatch(String, String),
     #[error("INVALID_SOURCE")]
     InvalidSource,
-    #[error("SOURCE_ERROR::{0}")]
-    SourceError(String),
+    #[error("INVALID_IDENTIFIER::{0}")]
+    InvalidIdentifier(String),
+    #[error("SOURCE_ERROR::{identifier}::{operation}::{error}")]
+    Source {
+        identifier: String,
+        operation: &'static str,
+        error: SourceError,
+    },
 
     #[error("COULD_NOT_LOAD_LIB")]
     LoadLib,
-    #[error("COULD_NOT_LOAD_FUNCTION")]
-    LoadFunction,
+    #[error("COULD_NOT_LOAD_FUNCTION::{0}")]
+    LoadFunction(String),
+    #[error("UNSUPPORTED_FUNCTION::{0}")]
+    UnsupportedFunction(String),
 
     #[error("COULD_NOT_SERIALIZE_LIB_RESPONSE")]
     SerializeResponse,
@@ -33,25 +44,198 @@ pub enum EbiError {
     CouldNotReadBuffer,
     #[error("COULD_NOT_SAVE_FILE::{0}")]
     CouldNotSaveFile(String),
-    #[error("INVALID_REQUEST::{0}")]
-    InvalidRequest(u16),
-    #[error("COULD_NOT_COMPLETE_REQUEST")]
-    CouldNotCompleteRequest,
+    #[error("INVALID_REQUEST::{status}::{url}")]
+    InvalidRequest { status: u16, url: String },
+    // Transport failures (dns, tls, timeouts...), the kind decides whether to retry
+    #[error("COULD_NOT_COMPLETE_REQUEST::{url}::{kind:?}::{message}")]
+    CouldNotCompleteRequest {
+        kind: ureq::ErrorKind,
+        url: String,
+        message: String,
+    },
 
     #[error("INVALID_DIR::{0}")]
     InvalidDir(String),
+    #[error("COULD_NOT_RESOLVE_DIR::{0}")]
+    UnresolvedDir(String),
+
+    #[error("INVALID_REPOSITORY_INDEX::{0}")]
+    InvalidRepositoryIndex(String),
+    #[error("SOURCE_NOT_IN_REPOSITORY::{0}")]
+    SourceNotInRepository(String),
+    #[error("SOURCE_NOT_INSTALLED::{0}")]
+    SourceNotInstalled(String),
+    #[error("UNSUPPORTED_TARGET::{0}")]
+    UnsupportedTarget(String),
+    #[error("INCOMPATIBLE_ABI_VERSION::{0}")]
+    IncompatibleAbiVersion(u32),
+    #[error("CHECKSUM_MISMATCH::{0}")]
+    ChecksumMismatch(String),
+
+    #[error("UNSIGNED_SOURCE::{0}")]
+    UnsignedSource(String),
+    #[error("INVALID_SIGNATURE::{0}")]
+    InvalidSignature(String),
+    #[error("UNTRUSTED_SOURCE_KEY::{0}::{1}")]
+    UntrustedSourceKey(String, String),
+    #[error("INVALID_PUBLIC_KEY::{0}")]
+    InvalidPublicKey(String),
+
+    #[error("INVALID_MANIFEST::{0}")]
+    InvalidManifest(String),
+
+    #[error("WATCHER_ERROR::{0}")]
+    Watcher(String),
+
+    #[error("OPERATION_TIMED_OUT::{0}")]
+    Timeout(String),
+    #[error("TASK_ERROR::{0}")]
+    Task(String),
+
+    #[error("INVALID_CONFIG::{0}")]
+    InvalidConfig(String),
+    #[error("INVALID_PREFERENCE::{0}")]
+    InvalidPreference(String),
+    #[error("CREDENTIAL_STORE_ERROR::{0}")]
+    CredentialStore(String),
+
+    #[error("NOT_AVAILABLE_OFFLINE::{0}")]
+    NotAvailableOffline(String),
 }
 
-impl std::convert::From<SourceError> for EbiError {
-    fn from(value: SourceError) -> Self {
-        match value {
-            SourceError::Unknown(msg) => Self::Unknown(msg),
-            SourceError::Fetch => todo!(),
-            SourceError::Serialize => todo!(),
-            SourceError::InvalidIdentifier => todo!(),
-            SourceError::ABINullConversion => Self::AbiSerialization,
-            SourceError::InvalidSource => todo!(),
-            SourceError::ABIResult(_) => todo!(),
+// Url of transport errors that are not tied to a request
+const UNKNOWN_URL: &str = "unknown";
+
+impl EbiError {
+    // SourceErrors are only converted with the source and operation that raised them
+    pub fn from_source(identifier: &str, operation: &'static str, error: SourceError) -> Self {
+        Self::Source {
+            identifier: identifier.to_owned(),
+            operation,
+            error,
+        }
+    }
+
+    // Stable, machine-readable code meant for frontends (e.g.: to pick a translated message)
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unknown(_) => "UNKNOWN_ERROR",
+            Self::DuplicatedSource(_) => "DUPLICATED_SOURCE",
+            Self::IdentifierMismatch(_, _) => "SOURCE_IDENTIFIER_MISMATCH",
+            Self::InvalidSource => "INVALID_SOURCE",
+            Self::InvalidIdentifier(_) => "INVALID_IDENTIFIER",
+            Self::Source { error, .. } => match error {
+                SourceError::Unknown(_) => "SOURCE_UNKNOWN_ERROR",
+                SourceError::Fetch => "SOURCE_FETCH_ERROR",
+                SourceError::Serialize => "SOURCE_SERIALIZE_ERROR",
+                SourceError::InvalidIdentifier => "SOURCE_INVALID_IDENTIFIER",
+                SourceError::InvalidSource => "SOURCE_INVALID_SOURCE",
+                SourceError::NotFound => "SOURCE_NOT_FOUND",
+                SourceError::RateLimited { .. } => "SOURCE_RATE_LIMITED",
+                SourceError::LoginRequired => "SOURCE_LOGIN_REQUIRED",
+                SourceError::Cloudflare => "SOURCE_CLOUDFLARE_PROTECTION",
+                SourceError::Http { .. } => "SOURCE_HTTP_ERROR",
+                SourceError::Uninitialized => "SOURCE_NOT_INITIALIZED",
+                SourceError::InvalidLocale(_) => "SOURCE_INVALID_LOCALE",
+                SourceError::InvalidPreferenceSchema(_) => "SOURCE_INVALID_PREFERENCE_SCHEMA",
+                SourceError::Panicked(_) => "SOURCE_PANICKED",
+                SourceError::ABINullConversion => "SOURCE_ABI_NULL_CONVERSION",
+                SourceError::ABIResult(_) => "SOURCE_ABI_RESULT_ERROR",
+            },
+            Self::LoadLib => "COULD_NOT_LOAD_LIB",
+            Self::LoadFunction(_) => "COULD_NOT_LOAD_FUNCTION",
+            Self::UnsupportedFunction(_) => "UNSUPPORTED_FUNCTION",
+            Self::SerializeResponse => "COULD_NOT_SERIALIZE_LIB_RESPONSE",
+            Self::AbiSerialization => "COULD_NOT_GENERATE_ABI_REPRESENTATION",
+            Self::NoContentType => "NO_CONTENT_TYPE_ON_RESPONSE_HEADERS",
+            Self::UnsupportedFile(_) => "UNSUPPORTED_FILE_FORMAT",
+            Self::CouldNotReadBuffer => "COULD_NOT_READ_BUFFER",
+            Self::CouldNotSaveFile(_) => "COULD_NOT_SAVE_FILE",
+            Self::InvalidRequest { .. } => "INVALID_REQUEST",
+            Self::CouldNotCompleteRequest { .. } => "COULD_NOT_COMPLETE_REQUEST",
+            Self::InvalidDir(_) => "INVALID_DIR",
+            Self::UnresolvedDir(_) => "COULD_NOT_RESOLVE_DIR",
+            Self::InvalidRepositoryIndex(_) => "INVALID_REPOSITORY_INDEX",
+            Self::SourceNotInRepository(_) => "SOURCE_NOT_IN_REPOSITORY",
+            Self::SourceNotInstalled(_) => "SOURCE_NOT_INSTALLED",
+            Self::UnsupportedTarget(_) => "UNSUPPORTED_TARGET",
+            Self::IncompatibleAbiVersion(_) => "INCOMPATIBLE_ABI_VERSION",
+            Self::ChecksumMismatch(_) => "CHECKSUM_MISMATCH",
+            Self::UnsignedSource(_) => "UNSIGNED_SOURCE",
+            Self::InvalidSignature(_) => "INVALID_SIGNATURE",
+            Self::UntrustedSourceKey(_, _) => "UNTRUSTED_SOURCE_KEY",
+            Self::InvalidPublicKey(_) => "INVALID_PUBLIC_KEY",
+            Self::InvalidManifest(_) => "INVALID_MANIFEST",
+            Self::Watcher(_) => "WATCHER_ERROR",
+            Self::Timeout(_) => "OPERATION_TIMED_OUT",
+            Self::Task(_) => "TASK_ERROR",
+            Self::InvalidConfig(_) => "INVALID_CONFIG",
+            Self::InvalidPreference(_) => "INVALID_PREFERENCE",
+            Self::CredentialStore(_) => "CREDENTIAL_STORE_ERROR",
+            Self::NotAvailableOffline(_) => "NOT_AVAILABLE_OFFLINE",
+        }
+    }
+
+    // Whether trying the same operation again may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout(_) => true,
+            Self::CouldNotCompleteRequest { kind, .. } => matches!(
+                kind,
+                ureq::ErrorKind::Dns
+                    | ureq::ErrorKind::ConnectionFailed
+                    | ureq::ErrorKind::Io
+                    | ureq::ErrorKind::ProxyConnect
+            ),
+            Self::InvalidRequest { status, .. } => {
+                *status == 408 || *status == 429 || *status >= 500
+            }
+            Self::Source { error, .. } => match error {
+                SourceError::Fetch | SourceError::RateLimited { .. } => true,
+                SourceError::Http { status, .. } => {
+                    *status == 408 || *status == 429 || *status >= 500
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // The source needs an account, see SourceManager::login
+    pub fn is_login_required(&self) -> bool {
+        matches!(
+            self,
+            Self::Source {
+                error: SourceError::LoginRequired,
+                ..
+            }
+        )
+    }
+
+    // Http status of the failed request, if any
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            Self::InvalidRequest { status, .. } => Some(*status),
+            Self::Source { error, .. } => error.http_status(),
+            _ => None,
+        }
+    }
+
+    // Seconds to wait before retrying, when the source reported it
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::Source { error, .. } => error.retry_after(),
+            _ => None,
+        }
+    }
+
+    // Url of the failed request, if any
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Self::InvalidRequest { url, .. } | Self::CouldNotCompleteRequest { url, .. } => {
+                Some(url)
+            }
+            _ => None,
         }
     }
 }
@@ -59,8 +243,26 @@ impl std::convert::From<SourceError> for EbiError {
 impl std::convert::From<ureq::Error> for EbiError {
     fn from(value: ureq::Error) -> Self {
         match value {
-            ureq::Error::Status(code, _) => Self::InvalidRequest(code),
-            ureq::Error::Transport(_) => Self::CouldNotCompleteRequest,
+            ureq::Error::Status(status, response) => Self::InvalidRequest {
+                status,
+                url: response.get_url().to_owned(),
+            },
+            ureq::Error::Transport(transport) => {
+                // The message is often empty, the underlying error (e.g.: io, tls) has the details
+                let message = transport
+                    .message()
+                    .map(str::to_owned)
+                    .or_else(|| std::error::Error::source(&transport).map(|e| e.to_string()))
+                    .unwrap_or_else(|| transport.kind().to_string());
+                Self::CouldNotCompleteRequest {
+                    kind: transport.kind(),
+                    url: transport
+                        .url()
+                        .map(|url| url.to_string())
+                        .unwrap_or_else(|| UNKNOWN_URL.to_owned()),
+                    message,
+                }
+            }
         }
     }
 }
@@ -70,3 +272,61 @@ impl std::convert::From<std::io::Error> for EbiError {
         Self::Unknown(format!("{}", value))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_errors_keep_their_context() {
+        let error = EbiError::from_source("example", "abi_search", SourceError::NotFound);
+        assert_eq!(
+            error.to_string(),
+            "SOURCE_ERROR::example::abi_search::NOT_FOUND"
+        );
+        assert_eq!(error.code(), "SOURCE_NOT_FOUND");
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn server_errors_are_retryable() {
+        let http = |status| SourceError::Http {
+            status,
+            message: String::new(),
+        };
+        assert!(EbiError::from_source("example", "abi_search", http(503)).is_retryable());
+        assert!(!EbiError::from_source("example", "abi_search", http(404)).is_retryable());
+        assert_eq!(
+            EbiError::from_source("example", "abi_search", http(404)).http_status(),
+            Some(404)
+        );
+    }
+
+    #[test]
+    fn transport_errors_keep_their_kind() {
+        let error = EbiError::from(ureq::Error::from(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "connection reset",
+        )));
+        assert!(matches!(
+            &error,
+            EbiError::CouldNotCompleteRequest {
+                kind: ureq::ErrorKind::Io,
+                message,
+                ..
+            } if message == "connection reset"
+        ));
+        assert_eq!(error.code(), "COULD_NOT_COMPLETE_REQUEST");
+        assert!(error.is_retryable());
+
+        let error = EbiError::from(ureq::get("unknown://example.com").call().unwrap_err());
+        assert!(matches!(
+            error,
+            EbiError::CouldNotCompleteRequest {
+                kind: ureq::ErrorKind::UnknownScheme,
+                ..
+            }
+        ));
+        assert!(!error.is_retryable());
+    }
+}
@@ -1,3 +1,4 @@
+pub mod config;
 pub(crate) mod downloader;
 pub mod error;
 pub mod sources;
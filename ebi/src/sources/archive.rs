@@ -1,19 +1,105 @@
-use std::{fs::DirEntry, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::{downloader::http_download, error::EbiError};
+use ebi_source::locale::Locale;
+use fs4::fs_std::FileExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
+use crate::{
+    downloader::{http_download, HttpClient, KnownFileExtensions},
+    error::EbiError,
+};
+
+use super::cookies::CookieStores;
+use super::migration::Migration;
 use super::{EbiChapter, EbiManga, SourceManager};
 
 const MANGA_COVER_FILE_NAME: &str = "cover";
+const CHAPTER_INDEX_FILE_NAME: &str = "index.json";
+const CHAPTER_INDEX_LOCK_FILE_NAME: &str = "index.lock";
+
+// A page saved in a chapter directory
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ArchivedPage {
+    pub page: u32,
+    pub file_name: String,
+    pub size: u64,
+    // Hex encoded SHA-256 of the file
+    pub sha256: String,
+    // Empty for pages saved before the index existed
+    pub url: String,
+}
+
+// A chapter of a chapter list, see SourceManager::chapter_list_with_downloads
+#[derive(Clone, Debug)]
+pub struct ListedChapter {
+    pub chapter: EbiChapter,
+    // Every page was saved with save_chapter, so it can be read offline
+    pub downloaded: bool,
+}
+
+// <chapter_path>/index.json, updated as each page is saved
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct ChapterIndex {
+    // Both set by save_chapter once every page is saved
+    chapter: Option<EbiChapter>,
+    page_count: Option<u32>,
+    pages: BTreeMap<u32, ArchivedPage>,
+}
+
+impl ChapterIndex {
+    // Chapters saved before the index existed are indexed from their files,
+    // named <page>.<extension>
+    fn from_files(chapter_path: &Path) -> Result<Self, EbiError> {
+        let mut index = Self::default();
+        for file in std::fs::read_dir(chapter_path)? {
+            let path = file?.path();
+            let page = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u32>().ok());
+            if let (Some(page), true) = (page, path.is_file()) {
+                index.pages.insert(page, archived_page(&path, page, "")?);
+            }
+        }
+        Ok(index)
+    }
+}
+
+fn archived_page(path: &Path, page: u32, url: &str) -> Result<ArchivedPage, EbiError> {
+    let file = std::fs::read(path)?;
+    Ok(ArchivedPage {
+        page,
+        file_name: path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        size: file.len() as u64,
+        sha256: hex::encode(Sha256::digest(&file)),
+        url: url.to_owned(),
+    })
+}
 
 pub struct SourceArchiver {
     dir_path: PathBuf,
+    cookie_stores: Arc<CookieStores>,
+    http: HttpClient,
+    // Nothing is downloaded, only saved files are returned
+    offline: bool,
 }
 
 impl std::convert::From<&SourceManager> for SourceArchiver {
     fn from(value: &SourceManager) -> Self {
         Self {
             dir_path: value.source_dir(),
+            cookie_stores: value.cookie_stores(),
+            http: value.http(),
+            offline: value.is_offline(),
         }
     }
 }
@@ -23,16 +109,24 @@ impl SourceArchiver {
         let manga_path = self.manga_path(&manga.source, &manga.identifier);
         std::fs::create_dir_all(&manga_path)?;
 
-        let find_patt = format!("/{MANGA_COVER_FILE_NAME}.");
-        let saved_cover = std::fs::read_dir(&manga_path)?.find_map(Self::find_map_file(&find_patt));
+        let saved_cover = std::fs::read_dir(&manga_path)?
+            .filter_map(|file| file.ok().map(|file| file.path()))
+            .find(|path| {
+                path.is_file()
+                    && path.file_stem().and_then(|stem| stem.to_str())
+                        == Some(MANGA_COVER_FILE_NAME)
+            });
 
         let mut manga = manga.clone();
-        manga.cover = self.download_if_not_exists(
-            saved_cover,
-            &manga.cover,
-            MANGA_COVER_FILE_NAME,
-            manga_path,
-        )?;
+        manga.cover = match saved_cover {
+            Some(saved_cover) => saved_cover.to_string_lossy().into_owned(),
+            None => self.download(
+                &manga.source,
+                &manga.cover,
+                MANGA_COVER_FILE_NAME,
+                &manga_path,
+            )?,
+        };
 
         Ok(manga)
     }
@@ -43,20 +137,169 @@ impl SourceArchiver {
         page: (&str, u32),
     ) -> Result<String, EbiError> {
         let chapter_path = self.chapter_path(chapter);
-        std::fs::create_dir_all(&chapter_path).unwrap();
+        // Pages saved without being indexed, e.g.: on a crash before the index was
+        // written, are indexed by save_chapter
+        if let Some(saved_page) = page_file(&chapter_path, page.1) {
+            return Ok(saved_page.to_string_lossy().into_owned());
+        }
 
-        let (page_url, page_number) = page;
+        let (saved_page, archived) = self.download_page(chapter, &chapter_path, page)?;
+        self.update_index(&chapter_path, |index| {
+            index.pages.insert(archived.page, archived);
+            Ok(())
+        })?;
 
-        let find_patt = format!("/{page_number}.");
-        let saved_page =
-            std::fs::read_dir(&chapter_path)?.find_map(Self::find_map_file(&find_patt));
+        Ok(saved_page)
+    }
 
-        self.download_if_not_exists(
-            saved_page,
-            &page_url,
-            &format!("{page_number}"),
-            chapter_path,
-        )
+    // Pages are numbered by their position in `pages`, as saved by save_chapter_pages.
+    // The index is written once, with the pages saved so far when a download fails.
+    // Returns the saved files
+    pub fn save_chapter(
+        &self,
+        chapter: &EbiChapter,
+        pages: &[String],
+    ) -> Result<Vec<String>, EbiError> {
+        let chapter_path = self.chapter_path(chapter);
+
+        let mut saved = Vec::with_capacity(pages.len());
+        let mut found = Vec::new();
+        let mut archived = Vec::new();
+        let mut failed = None;
+        for (page, url) in pages.iter().enumerate() {
+            let page = page as u32;
+            if let Some(saved_page) = page_file(&chapter_path, page) {
+                saved.push(saved_page.to_string_lossy().into_owned());
+                found.push((page, saved_page, url));
+                continue;
+            }
+            match self.download_page(chapter, &chapter_path, (url, page)) {
+                Ok((saved_page, page)) => {
+                    saved.push(saved_page);
+                    archived.push(page);
+                }
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+
+        self.update_index(&chapter_path, |index| {
+            for page in archived {
+                index.pages.insert(page.page, page);
+            }
+            // Saved before, possibly without being indexed or with an unknown url
+            for (page, path, url) in found {
+                match index.pages.get_mut(&page) {
+                    Some(archived) if archived.url.is_empty() => archived.url = url.clone(),
+                    Some(_) => {}
+                    None => {
+                        index.pages.insert(page, archived_page(&path, page, url)?);
+                    }
+                }
+            }
+            if failed.is_none() {
+                index.chapter = Some(chapter.clone());
+                index.page_count = Some(pages.len() as u32);
+            }
+            Ok(())
+        })?;
+
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(saved),
+        }
+    }
+
+    pub fn is_downloaded(&self, chapter: &EbiChapter) -> bool {
+        self.downloaded_pages(chapter).is_ok()
+    }
+
+    // Saved files of a chapter downloaded with save_chapter, in page order
+    pub fn downloaded_pages(&self, chapter: &EbiChapter) -> Result<Vec<String>, EbiError> {
+        let chapter_path = self.chapter_path(chapter);
+        let not_downloaded = || {
+            EbiError::NotAvailableOffline(format!(
+                "chapter_page_list::{}::{}::{}",
+                chapter.source, chapter.manga, chapter.chapter
+            ))
+        };
+        let index = saved_index(&chapter_path).ok_or_else(not_downloaded)?;
+        let page_count = index.page_count.ok_or_else(not_downloaded)?;
+
+        (0..page_count)
+            .map(|page| saved_page(&chapter_path, &index, page).ok_or_else(not_downloaded))
+            .collect()
+    }
+
+    // Indexed pages of a chapter, by page number. A missing or corrupt index is rebuilt
+    // from the page files and written, once
+    pub fn archived_pages(&self, chapter: &EbiChapter) -> Result<Vec<ArchivedPage>, EbiError> {
+        let chapter_path = self.chapter_path(chapter);
+        let index = match saved_index(&chapter_path) {
+            Some(index) => index,
+            None if chapter_path.is_dir() => self.update_index(&chapter_path, |_| Ok(()))?,
+            None => return Ok(Vec::new()),
+        };
+        Ok(index.pages.into_values().collect())
+    }
+
+    // Chapters of a manga downloaded with save_chapter, by chapter number
+    pub fn downloaded_chapters(&self, manga: &EbiManga) -> Result<Vec<EbiChapter>, EbiError> {
+        let manga_path = self.manga_path(&manga.source, &manga.identifier);
+        if !manga_path.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut chapters = Vec::new();
+        for dir in std::fs::read_dir(&manga_path)? {
+            let dir = dir?.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            let chapter = saved_index(&dir)
+                .filter(|index| index.page_count.is_some())
+                .and_then(|index| index.chapter);
+            if let Some(chapter) = chapter {
+                chapters.push(chapter);
+            }
+        }
+        chapters.sort_by(|a, b| a.chapter.total_cmp(&b.chapter));
+        Ok(chapters)
+    }
+
+    // Moves the downloaded pages of every matched chapter to the target manga, unless
+    // it already has them. Returns the target chapters now downloaded. Prefer
+    // SourceManager::migrate_downloads, which also updates the metadata cache
+    pub fn migrate_downloads(&self, migration: &Migration) -> Result<Vec<EbiChapter>, EbiError> {
+        let mut migrated = Vec::new();
+        for mapping in migration.chapters.iter() {
+            let to = match &mapping.to {
+                Some(to) => to,
+                None => continue,
+            };
+
+            let from_path = self.chapter_path(&mapping.from);
+            let to_path = self.chapter_path(to);
+            if !from_path.is_dir() || to_path.exists() {
+                continue;
+            }
+
+            if let Some(parent) = to_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(&from_path, &to_path)?;
+            self.update_index(&to_path, |index| {
+                if index.chapter.is_some() {
+                    index.chapter = Some(to.clone());
+                }
+                Ok(())
+            })?;
+            migrated.push(to.clone());
+        }
+
+        Ok(migrated)
     }
 }
 
@@ -73,45 +316,353 @@ impl SourceArchiver {
         manga_path
     }
 
+    // <chapter>_<language>, e.g.: "12_pt-BR", or <chapter> when the language is unknown
     fn chapter_path(&self, chapter: &EbiChapter) -> PathBuf {
-        let mut chapter_path = self.manga_path(&chapter.source, &chapter.manga);
-        chapter_path.push(format!("{}", chapter.chapter));
+        let manga_path = self.manga_path(&chapter.source, &chapter.manga);
+        let legacy_path = manga_path.join(chapter.chapter.to_string());
+        if chapter.language.is_unknown() {
+            return legacy_path;
+        }
+
+        let chapter_path = manga_path.join(format!("{}_{}", chapter.chapter, chapter.language));
+        // Saved before chapters were kept apart by language, without an index. Only a
+        // directory indexed with another language is a different chapter
+        let legacy = legacy_path.is_dir()
+            && indexed_language(&legacy_path).is_none_or(|language| language == chapter.language);
+        if !chapter_path.exists() && legacy {
+            return legacy_path;
+        }
         chapter_path
     }
 
-    fn download_if_not_exists(
+    // Returns the saved file
+    fn download(
         &self,
-        cached: Option<PathBuf>,
+        source: &str,
         url: &str,
         file_name: &str,
-        dir_path: PathBuf,
+        dir_path: &Path,
     ) -> Result<String, EbiError> {
-        match cached {
-            Some(cached_path) => Ok(cached_path.to_string_lossy().into_owned()),
-            None => {
-                let file_ext = http_download(&url, file_name, &dir_path)?;
+        if self.offline {
+            return Err(EbiError::NotAvailableOffline(url.to_owned()));
+        }
 
-                let mut f_path = dir_path;
-                f_path.push(format!("{file_name}.{file_ext}"));
+        let cookies = self.cookie_stores.get(source)?;
+        let request = self.http.get(url, Some(source));
+        let file_ext = http_download(request, file_name, dir_path, Some(&cookies))?;
 
-                Ok(f_path.to_string_lossy().into_owned())
+        let mut f_path = dir_path.to_path_buf();
+        f_path.push(format!("{file_name}.{file_ext}"));
+
+        Ok(f_path.to_string_lossy().into_owned())
+    }
+
+    // Downloads a page without indexing it. Returns the saved file and its index entry
+    fn download_page(
+        &self,
+        chapter: &EbiChapter,
+        chapter_path: &Path,
+        page: (&str, u32),
+    ) -> Result<(String, ArchivedPage), EbiError> {
+        let (page_url, page_number) = page;
+        std::fs::create_dir_all(chapter_path)?;
+
+        let saved_page = self.download(
+            &chapter.source,
+            page_url,
+            &format!("{page_number}"),
+            chapter_path,
+        )?;
+        let archived = archived_page(Path::new(&saved_page), page_number, page_url)?;
+        Ok((saved_page, archived))
+    }
+
+    // Re-reads the index from disk under <chapter_path>/index.lock before writing it,
+    // so archivers and processes saving the same chapter keep each other's pages.
+    // Returns the written index
+    fn update_index<F>(&self, chapter_path: &Path, update: F) -> Result<ChapterIndex, EbiError>
+    where
+        F: FnOnce(&mut ChapterIndex) -> Result<(), EbiError>,
+    {
+        std::fs::create_dir_all(chapter_path)?;
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(chapter_path.join(CHAPTER_INDEX_LOCK_FILE_NAME))?;
+        // Unlocked when `lock` is dropped
+        lock.lock_exclusive()?;
+
+        let mut index = read_index(chapter_path)?;
+        update(&mut index)?;
+        write_index(chapter_path, &index)?;
+        Ok(index)
+    }
+}
+
+// Saved file of the page, found without reading the index
+fn page_file(chapter_path: &Path, page: u32) -> Option<PathBuf> {
+    KnownFileExtensions::ALL
+        .iter()
+        .map(|file_ext| chapter_path.join(format!("{page}.{file_ext}")))
+        .find(|path| path.is_file())
+}
+
+// Indexed file of the page, if it is still there
+fn saved_page(chapter_path: &Path, index: &ChapterIndex, page: u32) -> Option<String> {
+    index
+        .pages
+        .get(&page)
+        .map(|page| chapter_path.join(&page.file_name))
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+// The index as written in index.json, without indexing the page files when it is
+// missing or unreadable: such chapters were never fully saved
+fn saved_index(chapter_path: &Path) -> Option<ChapterIndex> {
+    let index = std::fs::read(chapter_path.join(CHAPTER_INDEX_FILE_NAME)).ok()?;
+    serde_json::from_slice(&index).ok()
+}
+
+// Language of the chapter saved in `chapter_path`, once it was fully saved
+fn indexed_language(chapter_path: &Path) -> Option<Locale> {
+    saved_index(chapter_path)?
+        .chapter
+        .map(|chapter| chapter.language)
+}
+
+// Indexed from the page files when index.json is missing or corrupt, see update_index
+fn read_index(chapter_path: &Path) -> Result<ChapterIndex, EbiError> {
+    let path = chapter_path.join(CHAPTER_INDEX_FILE_NAME);
+    let index = match std::fs::read(&path) {
+        Ok(index) => match serde_json::from_slice(&index) {
+            Ok(index) => index,
+            Err(e) => {
+                log::warn!(
+                    "Could not read chapter index {} :: {}, indexing its files",
+                    path.display(),
+                    e
+                );
+                ChapterIndex::from_files(chapter_path)?
             }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            ChapterIndex::from_files(chapter_path)?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    Ok(index)
+}
+
+// Written to a temporary file first, so an interrupted write keeps the previous index
+fn write_index(chapter_path: &Path, index: &ChapterIndex) -> Result<(), EbiError> {
+    let path = chapter_path.join(CHAPTER_INDEX_FILE_NAME);
+    let tmp_path = path.with_extension("json.tmp");
+
+    let index = serde_json::to_vec(index).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))?;
+    std::fs::write(&tmp_path, index).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))?;
+    std::fs::rename(&tmp_path, &path).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::credentials::CredentialStore;
+
+    fn archiver(dir: &Path) -> SourceArchiver {
+        let credentials = Arc::new(CredentialStore::new(dir, None));
+        SourceArchiver {
+            dir_path: dir.to_path_buf(),
+            cookie_stores: Arc::new(CookieStores::new(dir.to_path_buf(), credentials)),
+            http: HttpClient::default(),
+            offline: true,
         }
     }
 
-    fn find_map_file(
-        pattern: &str,
-    ) -> Box<dyn FnMut(Result<DirEntry, std::io::Error>) -> Option<PathBuf>> {
-        let pattern = pattern.to_owned().clone();
-        Box::new(move |file| {
-            let file = file.ok()?;
-            let file_name = file.path();
-            let file_name = file_name.to_str()?;
+    fn chapter(chapter: f32, language: Locale) -> EbiChapter {
+        EbiChapter {
+            chapter,
+            title: String::new(),
+            url: String::new(),
+            manga: "manga".to_owned(),
+            source: "source".to_owned(),
+            language,
+        }
+    }
 
-            if file_name.contains(&pattern) {
-                return Some(file.path());
-            }
-            None
-        })
+    #[test]
+    fn chapters_are_kept_apart_by_language() {
+        let dir = tempfile::tempdir().unwrap();
+        let archiver = archiver(dir.path());
+        let manga_path = archiver.manga_path("source", "manga");
+
+        assert_eq!(
+            archiver.chapter_path(&chapter(12.0, Locale::PT_BR)),
+            manga_path.join("12_pt-BR")
+        );
+        assert_eq!(
+            archiver.chapter_path(&chapter(12.0, Locale::EN)),
+            manga_path.join("12_en")
+        );
+        assert_eq!(
+            archiver.chapter_path(&chapter(12.0, Locale::UNKNOWN)),
+            manga_path.join("12")
+        );
+    }
+
+    #[test]
+    fn chapters_saved_without_language_are_still_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let archiver = archiver(dir.path());
+        // As saved before chapters were kept apart by language: pages only
+        let legacy_path = archiver.manga_path("source", "manga").join("12");
+        std::fs::create_dir_all(&legacy_path).unwrap();
+        std::fs::write(legacy_path.join("0.png"), b"page").unwrap();
+
+        let pt_br = chapter(12.0, Locale::PT_BR);
+        assert_eq!(archiver.chapter_path(&pt_br), legacy_path);
+        assert_eq!(archiver.archived_pages(&pt_br).unwrap().len(), 1);
+
+        // Once indexed, only its language finds it
+        let index = ChapterIndex {
+            chapter: Some(pt_br.clone()),
+            page_count: Some(1),
+            pages: ChapterIndex::from_files(&legacy_path).unwrap().pages,
+        };
+        write_index(&legacy_path, &index).unwrap();
+        assert_eq!(archiver.chapter_path(&pt_br), legacy_path);
+        assert!(archiver.is_downloaded(&pt_br));
+        assert_ne!(
+            archiver.chapter_path(&chapter(12.0, Locale::EN)),
+            legacy_path
+        );
+    }
+
+    fn saved_chapter_path(archiver: &SourceArchiver) -> PathBuf {
+        let chapter_path = archiver.chapter_path(&chapter(1.0, Locale::EN));
+        std::fs::create_dir_all(&chapter_path).unwrap();
+        std::fs::write(chapter_path.join("0.png"), b"first").unwrap();
+        std::fs::write(chapter_path.join("1.jpg"), b"second").unwrap();
+        chapter_path
+    }
+
+    #[test]
+    fn from_files_indexes_only_page_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let chapter_path = saved_chapter_path(&archiver(dir.path()));
+        std::fs::write(chapter_path.join("cover.png"), b"cover").unwrap();
+        std::fs::write(chapter_path.join(CHAPTER_INDEX_LOCK_FILE_NAME), b"").unwrap();
+        std::fs::create_dir(chapter_path.join("2")).unwrap();
+
+        let index = ChapterIndex::from_files(&chapter_path).unwrap();
+        assert_eq!(index.pages.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(index.pages[&0].file_name, "0.png");
+        assert_eq!(index.pages[&1].size, 6);
+        assert_eq!(
+            index.pages[&0].sha256,
+            hex::encode(Sha256::digest(b"first"))
+        );
+        assert!(index.chapter.is_none());
+        assert!(index.page_count.is_none());
+    }
+
+    #[test]
+    fn corrupt_indexes_are_rebuilt_from_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let archiver = archiver(dir.path());
+        let chapter_path = saved_chapter_path(&archiver);
+        std::fs::write(chapter_path.join(CHAPTER_INDEX_FILE_NAME), b"{not json").unwrap();
+
+        let pages = archiver.archived_pages(&chapter(1.0, Locale::EN)).unwrap();
+        assert_eq!(pages.len(), 2);
+        // Written once rebuilt
+        assert_eq!(saved_index(&chapter_path).unwrap().pages.len(), 2);
+    }
+
+    #[test]
+    fn download_queries_do_not_write_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let archiver = archiver(dir.path());
+        let chapter_path = saved_chapter_path(&archiver);
+        let chapter = chapter(1.0, Locale::EN);
+
+        assert!(!archiver.is_downloaded(&chapter));
+        assert!(archiver
+            .downloaded_chapters(&EbiManga {
+                identifier: "manga".to_owned(),
+                title: String::new(),
+                cover: String::new(),
+                url: String::new(),
+                genres: Vec::new(),
+                description: None,
+                source: "source".to_owned(),
+            })
+            .unwrap()
+            .is_empty());
+        assert!(!chapter_path.join(CHAPTER_INDEX_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn saved_pages_are_indexed_without_downloading_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let archiver = archiver(dir.path());
+        saved_chapter_path(&archiver);
+        let chapter = chapter(1.0, Locale::EN);
+
+        let page = archiver.save_chapter_pages(&chapter, ("first", 0)).unwrap();
+        assert!(page.ends_with("0.png"));
+        let pages = ["first".to_owned(), "second".to_owned()];
+        let saved = archiver.save_chapter(&chapter, &pages).unwrap();
+        assert_eq!(saved.len(), 2);
+
+        assert_eq!(archiver.downloaded_pages(&chapter).unwrap(), saved);
+        let archived = archiver.archived_pages(&chapter).unwrap();
+        assert_eq!(
+            archived
+                .iter()
+                .map(|page| page.url.as_str())
+                .collect::<Vec<_>>(),
+            pages
+        );
+    }
+
+    #[test]
+    fn archivers_keep_each_other_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = archiver(dir.path());
+        let second = archiver(dir.path());
+        let chapter = chapter(1.0, Locale::EN);
+        let chapter_path = saved_chapter_path(&first);
+
+        // Both read the index before either writes it
+        assert_eq!(first.archived_pages(&chapter).unwrap().len(), 2);
+        assert_eq!(second.archived_pages(&chapter).unwrap().len(), 2);
+
+        std::fs::write(chapter_path.join("2.png"), b"third").unwrap();
+        let third = archived_page(&chapter_path.join("2.png"), 2, "third").unwrap();
+        first
+            .update_index(&chapter_path, |index| {
+                index.pages.insert(2, third);
+                Ok(())
+            })
+            .unwrap();
+        std::fs::write(chapter_path.join("3.png"), b"fourth").unwrap();
+        let fourth = archived_page(&chapter_path.join("3.png"), 3, "fourth").unwrap();
+        second
+            .update_index(&chapter_path, |index| {
+                index.pages.insert(3, fourth);
+                Ok(())
+            })
+            .unwrap();
+
+        let pages = first.archived_pages(&chapter).unwrap();
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| page.url.as_str())
+                .collect::<Vec<_>>(),
+            vec!["", "", "third", "fourth"]
+        );
     }
 }
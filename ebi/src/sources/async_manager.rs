@@ -0,0 +1,466 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ebi_source::SourceLoader;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::{OwnedRwLockReadGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::task::{AbortHandle, JoinHandle};
+
+use crate::error::EbiError;
+
+use super::archive::ListedChapter;
+use super::cache::CacheKey;
+use super::loader::Source;
+use super::manager::filter_chapters;
+use super::migration::{Migration, MigrationCandidate};
+use super::search::{SearchGroups, SourceSearchResult};
+use super::{EbiChapter, EbiManga, EbiSource, LoadReport, Locale, SourceManager};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Runs source calls on tokio's blocking thread pool, so callers (e.g.: a GUI) never block.
+// Sources written with `async fn` are polled by the caller's executor instead, falling
+// back to the blocking call (which logs back in) when their session expired.
+// Must be used from within a tokio runtime.
+//
+// Dropping a returned future cancels the call if it has not started yet (or drops the
+// source's future); a blocking call already running inside a source can't be
+// interrupted, so its result is discarded instead.
+//
+// Each call holds a read lock on the manager until the source returns, abandoned
+// blocking calls included, so sources are never unloaded while running.
+#[derive(Clone)]
+pub struct AsyncSourceManager {
+    manager: Arc<RwLock<SourceManager>>,
+    timeout: Duration,
+}
+
+impl From<SourceManager> for AsyncSourceManager {
+    fn from(manager: SourceManager) -> Self {
+        Self {
+            manager: Arc::new(RwLock::new(manager)),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+// Results of a global search, as each source answers. Dropping it cancels the
+// sources still searching
+pub struct AsyncGlobalSearch {
+    receiver: UnboundedReceiver<SourceSearchResult>,
+    _tasks: Vec<AbortOnDrop>,
+}
+
+impl AsyncGlobalSearch {
+    // None once every source answered
+    pub async fn next(&mut self) -> Option<SourceSearchResult> {
+        self.receiver.recv().await
+    }
+
+    // Waits for every source
+    pub async fn grouped(mut self) -> SearchGroups {
+        let mut groups = SearchGroups::default();
+        while let Some(result) = self.next().await {
+            groups.add(result);
+        }
+        groups
+    }
+}
+
+// Aborts the blocking task when the future awaiting it is dropped
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl AsyncSourceManager {
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Returns a handle to the same sources, whose calls time out after `timeout`
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            manager: self.manager.clone(),
+            timeout,
+        }
+    }
+
+    // Waits while sources are being (re)loaded
+    pub async fn read(&self) -> RwLockReadGuard<'_, SourceManager> {
+        self.manager.read().await
+    }
+
+    // Waits until every in-flight call is done, so sources can be safely (re)loaded
+    pub async fn write(&self) -> RwLockWriteGuard<'_, SourceManager> {
+        self.manager.write().await
+    }
+
+    pub async fn sources(&self) -> Result<Vec<EbiSource>, EbiError> {
+        let manager = self.lock().await;
+        self.spawn(manager, "sources", |manager| Ok(manager.sources()))
+            .await
+    }
+
+    pub async fn manga_list(&self, source: &str) -> Result<Vec<EbiManga>, EbiError> {
+        let manager = self.lock().await;
+        // Offline calls only read local files
+        if manager.is_offline() {
+            let source = source.to_owned();
+            return self
+                .spawn(manager, "manga_list", move |manager| {
+                    manager.manga_list(&source)
+                })
+                .await;
+        }
+
+        let key = CacheKey::manga_list(source);
+        let (manager, cached) = self
+            .cached(manager, key.clone(), |source| source.manga_list())
+            .await?;
+        if let Some((manga, _)) = cached {
+            return Ok(manga);
+        }
+
+        // Sources that are not loaded are left to the manager, which may have them cached
+        let loaded = manager.source(source).ok();
+        let future = loaded
+            .as_ref()
+            .and_then(|loaded| loaded.manga_list_future());
+        if let Some(future) = future {
+            match self.poll("manga_list", future).await {
+                Err(e) if e.is_login_required() => {}
+                result => {
+                    return self
+                        .cache_response(manager, "manga_list", key, result)
+                        .await
+                }
+            }
+        }
+
+        let source = source.to_owned();
+        self.spawn(manager, "manga_list", move |manager| {
+            manager.manga_list(&source)
+        })
+        .await
+    }
+
+    pub async fn search(&self, source: &str, query: &str) -> Result<Vec<EbiManga>, EbiError> {
+        self.search_with_age(source, query)
+            .await
+            .map(|(manga, _)| manga)
+    }
+
+    // Age of the results when they come from the cache
+    async fn search_with_age(
+        &self,
+        source: &str,
+        query: &str,
+    ) -> Result<(Vec<EbiManga>, Option<Duration>), EbiError> {
+        let manager = self.lock().await;
+        if manager.is_offline() {
+            let (source, query) = (source.to_owned(), query.to_owned());
+            return self
+                .spawn(manager, "search", move |manager| {
+                    manager.search_with_age(&source, &query)
+                })
+                .await;
+        }
+
+        let key = CacheKey::search(source, query);
+        let owned_query = query.to_owned();
+        let (manager, cached) = self
+            .cached(manager, key.clone(), move |source| {
+                source.search(&owned_query)
+            })
+            .await?;
+        if let Some((manga, age)) = cached {
+            return Ok((manga, Some(age)));
+        }
+
+        let loaded = manager.source(source).ok();
+        let future = loaded
+            .as_ref()
+            .and_then(|loaded| loaded.search_future(query));
+        if let Some(future) = future {
+            match self.poll("search", future).await {
+                Err(e) if e.is_login_required() => {}
+                result => {
+                    return self
+                        .spawn(manager, "search", move |manager| {
+                            manager.cache_response_with_age(&key, result)
+                        })
+                        .await
+                }
+            }
+        }
+
+        let (source, query) = (source.to_owned(), query.to_owned());
+        self.spawn(manager, "search", move |manager| {
+            manager.search_with_age(&source, &query)
+        })
+        .await
+    }
+
+    // Every loaded source supporting search
+    pub async fn global_search(&self, query: &str) -> AsyncGlobalSearch {
+        let sources = self.read().await.search_sources();
+        self.global_search_in(query, &sources)
+    }
+
+    // Each source is searched concurrently, and times out on its own
+    pub fn global_search_in(&self, query: &str, sources: &[String]) -> AsyncGlobalSearch {
+        let mut sources = sources.to_vec();
+        sources.sort();
+        sources.dedup();
+
+        let (sender, receiver) = unbounded_channel();
+        let tasks = sources
+            .into_iter()
+            .map(|source| {
+                let manager = self.clone();
+                let query = query.to_owned();
+                let sender = sender.clone();
+
+                let task = tokio::spawn(async move {
+                    let result = manager.search_with_age(&source, &query).await;
+                    let _ = sender.send(SourceSearchResult::new(source, result));
+                });
+                AbortOnDrop(task.abort_handle())
+            })
+            .collect();
+
+        AsyncGlobalSearch {
+            receiver,
+            _tasks: tasks,
+        }
+    }
+
+    pub async fn chapter_list(&self, manga: &EbiManga) -> Result<Vec<EbiChapter>, EbiError> {
+        let languages = self.read().await.languages().to_vec();
+        self.chapter_list_in(manga, &languages).await
+    }
+
+    pub async fn chapter_list_in(
+        &self,
+        manga: &EbiManga,
+        languages: &[Locale],
+    ) -> Result<Vec<EbiChapter>, EbiError> {
+        let manager = self.lock().await;
+        if manager.is_offline() {
+            let (manga, languages) = (manga.clone(), languages.to_vec());
+            return self
+                .spawn(manager, "chapter_list", move |manager| {
+                    manager.chapter_list_in(&manga, &languages)
+                })
+                .await;
+        }
+
+        let key = CacheKey::chapter_list(manga, languages);
+        let (owned_manga, preferred) = (manga.clone(), languages.to_vec());
+        let (manager, cached) = self
+            .cached(manager, key.clone(), move |source| {
+                source.chapter_list(&owned_manga, &preferred)
+            })
+            .await?;
+        if let Some((chapters, _)) = cached {
+            return Ok(filter_chapters(chapters, languages));
+        }
+
+        let loaded = manager.source(&manga.source).ok();
+        let future = loaded
+            .as_ref()
+            .and_then(|loaded| loaded.chapter_list_future(manga, languages));
+        if let Some(future) = future {
+            match self.poll("chapter_list", future).await {
+                Err(e) if e.is_login_required() => {}
+                result => {
+                    let result = self
+                        .cache_response(manager, "chapter_list", key, result)
+                        .await;
+                    return result.map(|chapters| filter_chapters(chapters, languages));
+                }
+            }
+        }
+
+        let manga = manga.clone();
+        let languages = languages.to_vec();
+        self.spawn(manager, "chapter_list", move |manager| {
+            manager.chapter_list_in(&manga, &languages)
+        })
+        .await
+    }
+
+    // See SourceManager::chapter_list_with_downloads
+    pub async fn chapter_list_with_downloads(
+        &self,
+        manga: &EbiManga,
+    ) -> Result<Vec<ListedChapter>, EbiError> {
+        let chapters = self.chapter_list(manga).await?;
+        let manager = self.lock().await;
+        self.spawn(manager, "mark_downloaded", move |manager| {
+            Ok(manager.mark_downloaded(chapters))
+        })
+        .await
+    }
+
+    pub async fn chapter_page_list(&self, chapter: &EbiChapter) -> Result<Vec<String>, EbiError> {
+        let manager = self.lock().await;
+        if manager.is_offline() {
+            let chapter = chapter.clone();
+            return self
+                .spawn(manager, "chapter_page_list", move |manager| {
+                    manager.chapter_page_list(&chapter)
+                })
+                .await;
+        }
+
+        let key = CacheKey::chapter_page_list(chapter);
+        let owned_chapter = chapter.clone();
+        let (manager, cached) = self
+            .cached(manager, key.clone(), move |source| {
+                source.chapter_page_list(&owned_chapter)
+            })
+            .await?;
+        if let Some((pages, _)) = cached {
+            return Ok(pages);
+        }
+
+        let loaded = manager.source(&chapter.source).ok();
+        let future = loaded
+            .as_ref()
+            .and_then(|loaded| loaded.chapter_page_list_future(chapter));
+        if let Some(future) = future {
+            match self.poll("chapter_page_list", future).await {
+                Err(e) if e.is_login_required() => {}
+                result => {
+                    return self
+                        .cache_response(manager, "chapter_page_list", key, result)
+                        .await
+                }
+            }
+        }
+
+        let chapter = chapter.clone();
+        self.spawn(manager, "chapter_page_list", move |manager| {
+            manager.chapter_page_list(&chapter)
+        })
+        .await
+    }
+
+    pub async fn migration_candidates(
+        &self,
+        manga: &EbiManga,
+        target: &str,
+    ) -> Result<Vec<MigrationCandidate>, EbiError> {
+        let found = self.search(target, &manga.title).await?;
+        Ok(MigrationCandidate::ranked(manga, found))
+    }
+
+    pub async fn migrate(&self, from: &EbiManga, to: &EbiManga) -> Result<Migration, EbiError> {
+        let chapters = self.chapter_list_in(from, &[]).await?;
+        self.migrate_chapters(from, chapters, to).await
+    }
+
+    pub async fn migrate_chapters(
+        &self,
+        from: &EbiManga,
+        chapters: Vec<EbiChapter>,
+        to: &EbiManga,
+    ) -> Result<Migration, EbiError> {
+        let target_chapters = self.chapter_list(to).await?;
+        Ok(Migration::new(
+            from.clone(),
+            chapters,
+            to.clone(),
+            &target_chapters,
+        ))
+    }
+
+    pub async fn load_sources(&self) -> Result<LoadReport, EbiError> {
+        let mut manager = self.manager.clone().write_owned().await;
+        let task = tokio::task::spawn_blocking(move || manager.load_sources());
+
+        // Not bound by the call timeout: loading must not be abandoned half-way
+        task.await
+            .map_err(|e| EbiError::Task(format!("load_sources :: {}", e)))?
+    }
+
+    // Kept by the call until the source returns, see `spawn`
+    async fn lock(&self) -> OwnedRwLockReadGuard<SourceManager> {
+        self.manager.clone().read_owned().await
+    }
+
+    async fn poll<T, F>(&self, operation: &'static str, future: F) -> Result<T, EbiError>
+    where
+        F: Future<Output = Result<T, EbiError>>,
+    {
+        tokio::time::timeout(self.timeout, future)
+            .await
+            .map_err(|_| EbiError::Timeout(operation.to_owned()))?
+    }
+
+    // Cache files are read on a blocking thread, which hands the read lock back
+    async fn cached<T, F>(
+        &self,
+        manager: OwnedRwLockReadGuard<SourceManager>,
+        key: CacheKey,
+        call: F,
+    ) -> Result<(OwnedRwLockReadGuard<SourceManager>, Option<(T, Duration)>), EbiError>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: Fn(&Source) -> Result<T, EbiError> + Send + 'static,
+    {
+        tokio::task::spawn_blocking(move || {
+            let cached = manager.cached_with_age(&key, call);
+            (manager, cached)
+        })
+        .await
+        .map_err(|e| EbiError::Task(format!("cache :: {}", e)))
+    }
+
+    // Stores the response of a source future, see SourceManager::cache_response
+    async fn cache_response<T>(
+        &self,
+        manager: OwnedRwLockReadGuard<SourceManager>,
+        operation: &'static str,
+        key: CacheKey,
+        result: Result<T, EbiError>,
+    ) -> Result<T, EbiError>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+    {
+        self.spawn(manager, operation, move |manager| {
+            manager.cache_response(&key, result)
+        })
+        .await
+    }
+
+    // The read lock moves into the blocking task, so a timed out call keeps it until
+    // the source returns, and writers wait for it without blocking the runtime
+    async fn spawn<T, F>(
+        &self,
+        manager: OwnedRwLockReadGuard<SourceManager>,
+        operation: &'static str,
+        call: F,
+    ) -> Result<T, EbiError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&SourceManager) -> Result<T, EbiError> + Send + 'static,
+    {
+        let task: JoinHandle<Result<T, EbiError>> =
+            tokio::task::spawn_blocking(move || call(&manager));
+        let _abort = AbortOnDrop(task.abort_handle());
+
+        match tokio::time::timeout(self.timeout, task).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => Err(EbiError::Task(format!("{} :: {}", operation, e))),
+            Err(_) => Err(EbiError::Timeout(operation.to_owned())),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use crate::{config::EbiConfig, downloader::HttpClient, error::EbiError};
+
+use super::credentials::{default_credential_key, CredentialKey};
+use super::SourceManager;
+
+const APP_DIR_NAME: &str = "ebi";
+const LEGACY_DIR_NAME: &str = ".ebi";
+
+const DATA_DIR_ENV: &str = "EBI_DATA_DIR";
+const CACHE_DIR_ENV: &str = "EBI_CACHE_DIR";
+const CONFIG_DIR_ENV: &str = "EBI_CONFIG_DIR";
+
+// Each directory is resolved from, in order:
+// 1. the value given to the builder;
+// 2. its environment variable (EBI_DATA_DIR, EBI_CACHE_DIR, EBI_CONFIG_DIR);
+// 3. $HOME/.ebi, if it already exists (layout used by older versions);
+// 4. the platform directories (XDG base directories on Linux).
+// Credentials are encrypted with a key from the OS keyring, unless another one is set
+#[derive(Clone, Debug, Default)]
+pub struct SourceManagerBuilder {
+    data_dir: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    config_dir: Option<PathBuf>,
+    credential_key: Option<CredentialKey>,
+    offline: bool,
+}
+
+impl SourceManagerBuilder {
+    pub fn data_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.data_dir = Some(dir.into());
+        self
+    }
+
+    pub fn cache_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    pub fn config_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.config_dir = Some(dir.into());
+        self
+    }
+
+    pub fn credential_key(mut self, key: CredentialKey) -> Self {
+        self.credential_key = Some(key);
+        self
+    }
+
+    // See SourceManager::set_offline
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn build(self) -> Result<SourceManager, EbiError> {
+        let data_dir = resolve_dir(self.data_dir, DATA_DIR_ENV, dirs::data_dir)?;
+        let cache_dir = resolve_dir(self.cache_dir, CACHE_DIR_ENV, dirs::cache_dir)?;
+        let config_dir = resolve_dir(self.config_dir, CONFIG_DIR_ENV, dirs::config_dir)?;
+
+        for dir in [&data_dir, &cache_dir, &config_dir] {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| EbiError::InvalidDir(format!("{} :: {}", dir.display(), e)))?;
+        }
+
+        let config = EbiConfig::load(&config_dir)?;
+        let http = HttpClient::new(&config.http)?;
+        let credential_key = self.credential_key.or_else(default_credential_key);
+        let mut manager = SourceManager::with_dirs(
+            data_dir,
+            cache_dir,
+            config_dir,
+            config,
+            http,
+            credential_key,
+        );
+        manager.set_offline(self.offline);
+        Ok(manager)
+    }
+}
+
+fn resolve_dir(
+    dir: Option<PathBuf>,
+    env: &str,
+    platform_dir: fn() -> Option<PathBuf>,
+) -> Result<PathBuf, EbiError> {
+    if let Some(dir) = dir {
+        return Ok(dir);
+    }
+
+    if let Some(dir) = std::env::var_os(env).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    if let Some(mut legacy_dir) = dirs::home_dir() {
+        legacy_dir.push(LEGACY_DIR_NAME);
+        if legacy_dir.is_dir() {
+            return Ok(legacy_dir);
+        }
+    }
+
+    let mut dir = platform_dir().ok_or_else(|| EbiError::UnresolvedDir(env.to_owned()))?;
+    dir.push(APP_DIR_NAME);
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directories_set_on_the_builder_are_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = SourceManager::builder()
+            .data_dir(dir.path().join("data"))
+            .cache_dir(dir.path().join("cache"))
+            .config_dir(dir.path().join("config"))
+            .build()
+            .unwrap();
+
+        assert_eq!(manager.data_dir(), dir.path().join("data"));
+        assert_eq!(manager.cache_dir(), dir.path().join("cache"));
+        assert_eq!(manager.config_dir(), dir.path().join("config"));
+        assert!(dir.path().join("config").is_dir());
+    }
+
+    #[test]
+    fn new_fails_like_the_builder_on_an_invalid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(EbiConfig::path(dir.path()), "{").unwrap();
+
+        let built = SourceManager::builder()
+            .data_dir(dir.path())
+            .cache_dir(dir.path())
+            .config_dir(dir.path())
+            .build();
+        assert!(matches!(built, Err(EbiError::InvalidConfig(_))));
+
+        let path = dir.path().to_str().unwrap().to_owned();
+        assert!(std::panic::catch_unwind(|| SourceManager::new(&path)).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use ebi_source::util::now;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::EbiError;
+
+use super::{lock, validate_identifier, EbiChapter, EbiManga, Locale};
+
+// Entries kept in memory, the rest are read back from disk when needed
+const MAX_ENTRIES: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CacheOperation {
+    MangaList,
+    Search,
+    ChapterList,
+    ChapterPageList,
+}
+
+impl CacheOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MangaList => "manga_list",
+            Self::Search => "search",
+            Self::ChapterList => "chapter_list",
+            Self::ChapterPageList => "chapter_page_list",
+        }
+    }
+}
+
+// A source response, by the arguments of the call. Chapter and page lists start
+// with the manga identifier, so they can be invalidated by manga
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub source: String,
+    pub operation: CacheOperation,
+    pub args: Vec<String>,
+}
+
+impl CacheKey {
+    pub fn manga_list(source: &str) -> Self {
+        Self::new(source, CacheOperation::MangaList, vec![])
+    }
+
+    pub fn search(source: &str, query: &str) -> Self {
+        Self::new(source, CacheOperation::Search, vec![query.to_owned()])
+    }
+
+    pub fn chapter_list(manga: &EbiManga, languages: &[Locale]) -> Self {
+        let mut languages = languages
+            .iter()
+            .map(|language| language.to_string())
+            .collect::<Vec<String>>();
+        languages.sort();
+
+        let mut args = vec![manga.identifier.clone(), manga.url.clone()];
+        args.append(&mut languages);
+        Self::new(&manga.source, CacheOperation::ChapterList, args)
+    }
+
+    pub fn chapter_page_list(chapter: &EbiChapter) -> Self {
+        let args = vec![
+            chapter.manga.clone(),
+            chapter.chapter.to_string(),
+            chapter.url.clone(),
+        ];
+        Self::new(&chapter.source, CacheOperation::ChapterPageList, args)
+    }
+
+    fn new(source: &str, operation: CacheOperation, args: Vec<String>) -> Self {
+        Self {
+            source: source.to_owned(),
+            operation,
+            args,
+        }
+    }
+
+    // <dir>/<source>/<operation>/<sha256 of the arguments>.json. The source comes from
+    // plugin responses (e.g.: manga.source), so it must be a valid identifier
+    fn path(&self, dir: &Path) -> Result<PathBuf, EbiError> {
+        validate_identifier(&self.source)?;
+
+        let mut hasher = Sha256::new();
+        for arg in self.args.iter() {
+            hasher.update(arg.as_bytes());
+            hasher.update([0]);
+        }
+
+        let mut path = dir.to_path_buf();
+        path.push(&self.source);
+        path.push(self.operation.as_str());
+        path.push(format!("{}.json", hex::encode(hasher.finalize())));
+        Ok(path)
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct CacheEntry {
+    // Kept to tell apart arguments with the same hash
+    args: Vec<String>,
+    // Seconds since the epoch
+    stored_at: u64,
+    value: serde_json::Value,
+}
+
+// Source responses written through to <cache_dir>/metadata, so they survive restarts,
+// with up to MAX_ENTRIES of them kept in memory. Freshness is decided by the manager, from config.cache
+pub struct MetadataCache {
+    dir: PathBuf,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    revalidating: Mutex<HashSet<CacheKey>>,
+}
+
+impl MetadataCache {
+    pub fn new(cache_dir: &Path) -> Self {
+        let mut dir = cache_dir.to_path_buf();
+        dir.push("metadata");
+        Self {
+            dir,
+            entries: Mutex::new(HashMap::new()),
+            revalidating: Mutex::new(HashSet::new()),
+        }
+    }
+
+    // Value and age of the stored response, whatever its age
+    pub fn get<T: DeserializeOwned>(&self, key: &CacheKey) -> Option<(T, Duration)> {
+        let mut entries = lock(&self.entries);
+        if !entries.contains_key(key) {
+            let entry = self.read_entry(key)?;
+            insert_entry(&mut entries, key, entry);
+        }
+
+        let entry = entries.get(key)?;
+        let value = serde_json::from_value(entry.value.clone()).ok()?;
+        let age = Duration::from_secs(now().saturating_sub(entry.stored_at));
+        Some((value, age))
+    }
+
+    pub fn insert<T: Serialize>(&self, key: &CacheKey, value: &T) {
+        let value = match serde_json::to_value(value) {
+            Ok(value) => value,
+            Err(e) => {
+                log::warn!("Could not cache {} :: {}", key.operation.as_str(), e);
+                return;
+            }
+        };
+
+        let entry = CacheEntry {
+            args: key.args.clone(),
+            stored_at: now(),
+            value,
+        };
+        if let Err(e) = self.write_entry(key, &entry) {
+            log::warn!("Could not save cached {} :: {}", key.operation.as_str(), e);
+        }
+        insert_entry(&mut lock(&self.entries), key, entry);
+    }
+
+    // Every response of a source, or only the ones of an operation
+    pub fn invalidate(
+        &self,
+        source: &str,
+        operation: Option<CacheOperation>,
+    ) -> Result<(), EbiError> {
+        validate_identifier(source)?;
+        let mut entries = lock(&self.entries);
+        entries.retain(|key, _| {
+            key.source != source || operation.is_some_and(|operation| key.operation != operation)
+        });
+
+        let mut path = self.dir.clone();
+        path.push(source);
+        if let Some(operation) = operation {
+            path.push(operation.as_str());
+        }
+        remove_dir(&path)
+    }
+
+    // Responses of an operation whose first argument is `manga`
+    pub fn invalidate_manga(
+        &self,
+        source: &str,
+        operation: CacheOperation,
+        manga: &str,
+    ) -> Result<(), EbiError> {
+        validate_identifier(source)?;
+        let is_manga = |args: &[String]| args.first().is_some_and(|arg| arg == manga);
+
+        let mut entries = lock(&self.entries);
+        entries.retain(|key, _| {
+            key.source != source || key.operation != operation || !is_manga(&key.args)
+        });
+
+        let mut dir = self.dir.clone();
+        dir.push(source);
+        dir.push(operation.as_str());
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        for file in std::fs::read_dir(&dir)? {
+            let path = file?.path();
+            let entry = std::fs::read(&path)
+                .ok()
+                .and_then(|entry| serde_json::from_slice::<CacheEntry>(&entry).ok());
+            // Unreadable entries are removed as well
+            if entry.is_none_or(|entry| is_manga(&entry.args)) {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), EbiError> {
+        lock(&self.entries).clear();
+        remove_dir(&self.dir)
+    }
+
+    // False when the response is already being refreshed
+    pub fn start_revalidation(&self, key: &CacheKey) -> bool {
+        lock(&self.revalidating).insert(key.clone())
+    }
+
+    pub fn end_revalidation(&self, key: &CacheKey) {
+        lock(&self.revalidating).remove(key);
+    }
+
+    fn read_entry(&self, key: &CacheKey) -> Option<CacheEntry> {
+        let path = key.path(&self.dir).ok()?;
+        let entry = std::fs::read(&path).ok()?;
+        let entry: CacheEntry = match serde_json::from_slice(&entry) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Could not read cache entry {} :: {}", path.display(), e);
+                return None;
+            }
+        };
+        Some(entry).filter(|entry| entry.args == key.args)
+    }
+
+    fn write_entry(&self, key: &CacheKey, entry: &CacheEntry) -> Result<(), EbiError> {
+        let path = key.path(&self.dir)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let entry =
+            serde_json::to_vec(entry).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))?;
+        std::fs::write(&path, entry).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))
+    }
+}
+
+// Makes room by dropping the oldest entries, which stay on disk
+fn insert_entry(entries: &mut HashMap<CacheKey, CacheEntry>, key: &CacheKey, entry: CacheEntry) {
+    while entries.len() >= MAX_ENTRIES && !entries.contains_key(key) {
+        let oldest = entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.stored_at)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(oldest) => entries.remove(&oldest),
+            None => break,
+        };
+    }
+    entries.insert(key.clone(), entry);
+}
+
+fn remove_dir(path: &Path) -> Result<(), EbiError> {
+    if path.exists() {
+        std::fs::remove_dir_all(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(source: &str, query: &str) -> CacheKey {
+        CacheKey::search(source, query)
+    }
+
+    #[test]
+    fn entries_are_written_through_and_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        MetadataCache::new(dir.path()).insert(&key("source", "one piece"), &vec![1, 2]);
+
+        let cache = MetadataCache::new(dir.path());
+        let (value, age): (Vec<i32>, Duration) = cache.get(&key("source", "one piece")).unwrap();
+        assert_eq!(value, [1, 2]);
+        assert!(age < Duration::from_secs(5));
+        assert!(cache.get::<Vec<i32>>(&key("source", "naruto")).is_none());
+    }
+
+    #[test]
+    fn a_panic_holding_the_cache_does_not_poison_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = std::sync::Arc::new(MetadataCache::new(dir.path()));
+        let poisoned = cache.clone();
+        let _ = std::thread::spawn(move || {
+            let _entries = lock(&poisoned.entries);
+            panic!("source call");
+        })
+        .join();
+        assert!(cache.entries.is_poisoned());
+
+        cache.insert(&key("source", "query"), &1);
+        let (value, _): (i32, _) = cache.get(&key("source", "query")).unwrap();
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn only_recent_entries_stay_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MetadataCache::new(dir.path());
+        for query in 0..MAX_ENTRIES + 10 {
+            cache.insert(&key("source", &query.to_string()), &query);
+        }
+        assert_eq!(lock(&cache.entries).len(), MAX_ENTRIES);
+
+        // Evicted entries are still on disk
+        for query in [0, MAX_ENTRIES + 9] {
+            let (value, _): (usize, _) = cache.get(&key("source", &query.to_string())).unwrap();
+            assert_eq!(value, query);
+        }
+        assert_eq!(lock(&cache.entries).len(), MAX_ENTRIES);
+    }
+
+    #[test]
+    fn invalid_sources_are_never_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let cache = MetadataCache::new(&cache_dir);
+
+        cache.insert(&key("../../outside", "query"), &1);
+        assert!(!dir.path().join("outside").exists());
+        assert!(key("../../outside", "query").path(&cache.dir).is_err());
+
+        std::fs::create_dir_all(dir.path().join("keep")).unwrap();
+        assert!(matches!(
+            cache.invalidate("../../keep", None),
+            Err(EbiError::InvalidIdentifier(_))
+        ));
+        assert!(matches!(
+            cache.invalidate_manga("..", CacheOperation::ChapterList, "manga"),
+            Err(EbiError::InvalidIdentifier(_))
+        ));
+        assert!(dir.path().join("keep").exists());
+    }
+
+    #[test]
+    fn invalidates_by_source_operation_and_manga() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MetadataCache::new(dir.path());
+        let manga = |identifier: &str| EbiManga {
+            identifier: identifier.to_owned(),
+            title: String::new(),
+            cover: String::new(),
+            url: String::new(),
+            genres: Vec::new(),
+            description: None,
+            source: "source".to_owned(),
+        };
+        let (a, b) = (
+            CacheKey::chapter_list(&manga("a"), &[]),
+            CacheKey::chapter_list(&manga("b"), &[]),
+        );
+        cache.insert(&a, &1);
+        cache.insert(&b, &2);
+        cache.insert(&key("source", "query"), &3);
+
+        cache
+            .invalidate_manga("source", CacheOperation::ChapterList, "a")
+            .unwrap();
+        let cache = MetadataCache::new(dir.path());
+        assert!(cache.get::<i32>(&a).is_none());
+        assert!(cache.get::<i32>(&b).is_some());
+
+        cache
+            .invalidate("source", Some(CacheOperation::Search))
+            .unwrap();
+        assert!(cache.get::<i32>(&key("source", "query")).is_none());
+        assert!(cache.get::<i32>(&b).is_some());
+    }
+}
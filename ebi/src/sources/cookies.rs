@@ -0,0 +1,382 @@
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, Weak},
+    time::Duration,
+};
+
+use ebi_source::{
+    abi::{cookies::ABICookieJar, primitives::FFIString},
+    cookies::{cookie_header, url_host, Cookie},
+    util::now,
+};
+use publicsuffix::{List, Psl};
+
+use crate::error::EbiError;
+
+use super::credentials::CredentialStore;
+use super::{lock, validate_identifier};
+
+// Changes made within this interval are written together
+const SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+// <data_dir>/cookies/<identifier>.bin
+pub fn cookies_path<P: AsRef<Path>>(data_dir: P, identifier: &str) -> PathBuf {
+    let mut path = data_dir.as_ref().to_path_buf();
+    path.push("cookies");
+    path.push(format!("{}.bin", identifier));
+    path
+}
+
+// https://publicsuffix.org/list/public_suffix_list.dat
+const PUBLIC_SUFFIX_LIST: &[u8] = include_bytes!("../../data/public_suffix_list.dat");
+
+// e.g.: "com", "co.uk", "github.io"
+fn is_public_suffix(domain: &str) -> bool {
+    static LIST: OnceLock<List> = OnceLock::new();
+    let list = LIST.get_or_init(|| List::from_bytes(PUBLIC_SUFFIX_LIST).unwrap_or_default());
+    list.suffix(domain.as_bytes())
+        .is_some_and(|suffix| suffix.as_bytes() == domain.as_bytes())
+}
+
+type Stores = Mutex<HashMap<String, Arc<CookieStore>>>;
+
+// Stores by source identifier, created on first use whether the source is loaded or not.
+// Shared by the manager and archivers, so every user of a source has the same session
+pub struct CookieStores {
+    data_dir: PathBuf,
+    // Encrypts the files, like credentials
+    credentials: Arc<CredentialStore>,
+    stores: Arc<Stores>,
+}
+
+impl CookieStores {
+    pub fn new(data_dir: PathBuf, credentials: Arc<CredentialStore>) -> Self {
+        let stores = Arc::new(Stores::default());
+        spawn_saver(Arc::downgrade(&stores));
+
+        Self {
+            data_dir,
+            credentials,
+            stores,
+        }
+    }
+
+    pub fn get(&self, identifier: &str) -> Result<Arc<CookieStore>, EbiError> {
+        validate_identifier(identifier)?;
+
+        let mut stores = lock(&self.stores);
+        let store = stores.entry(identifier.to_owned()).or_insert_with(|| {
+            Arc::new(CookieStore::load(
+                cookies_path(&self.data_dir, identifier),
+                identifier,
+                self.credentials.clone(),
+            ))
+        });
+        Ok(store.clone())
+    }
+}
+
+impl Drop for CookieStores {
+    fn drop(&mut self) {
+        for store in lock(&self.stores).values() {
+            let _ = store.flush();
+        }
+    }
+}
+
+// Writes changed stores every SAVE_INTERVAL, until the stores are dropped. Sources set
+// cookies from their calls, which should not wait for the key or the disk
+fn spawn_saver(stores: Weak<Stores>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SAVE_INTERVAL);
+        let stores = match stores.upgrade() {
+            Some(stores) => lock(&stores).values().cloned().collect::<Vec<_>>(),
+            None => return,
+        };
+        for store in stores {
+            // Reported by save_error, and logged once by flush
+            let _ = store.flush();
+        }
+    });
+}
+
+struct Cookies {
+    cookies: Vec<Cookie>,
+    // Changed since the last write
+    dirty: bool,
+    // Why the last write failed, cleared once written
+    save_error: Option<String>,
+}
+
+// Cookies of a single source, shared by the source and the downloader. Changes are
+// written (encrypted) to disk within SAVE_INTERVAL, and when the store is dropped, so
+// sessions survive restarts
+pub struct CookieStore {
+    path: PathBuf,
+    identifier: String,
+    credentials: Arc<CredentialStore>,
+    cookies: Mutex<Cookies>,
+    // Held while writing, so an older snapshot never replaces a newer one
+    saving: Mutex<()>,
+}
+
+impl CookieStore {
+    // A missing or unreadable file gives an empty store
+    fn load(path: PathBuf, identifier: &str, credentials: Arc<CredentialStore>) -> Self {
+        let aad = format!("cookies:{}", identifier);
+        let cookies = match credentials.read_sealed(&path, &aad) {
+            Ok(Some(cookies)) => serde_json::from_slice(&cookies).map_err(|e| e.to_string()),
+            Ok(None) => Ok(Vec::new()),
+            Err(e) => Err(e.to_string()),
+        };
+        let cookies = cookies.unwrap_or_else(|e| {
+            log::warn!("Could not read cookies at {} :: {}", path.display(), e);
+            Vec::new()
+        });
+
+        Self {
+            path,
+            identifier: identifier.to_owned(),
+            credentials,
+            cookies: Mutex::new(Cookies {
+                cookies,
+                dirty: false,
+                save_error: None,
+            }),
+            saving: Mutex::new(()),
+        }
+    }
+
+    // Unexpired cookies
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let now = now();
+        lock(&self.cookies)
+            .cookies
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    pub fn cookie_header(&self, url: &str) -> Option<String> {
+        cookie_header(&lock(&self.cookies).cookies, url)
+    }
+
+    // Invalid headers are ignored, as a browser would. Only kept in memory, see flush
+    pub fn set_cookie(&self, url: &str, set_cookie: &str) {
+        let mut cookie = match Cookie::parse(set_cookie, url) {
+            Some(cookie) => cookie,
+            None => {
+                log::debug!("Ignoring invalid cookie from {}", url);
+                return;
+            }
+        };
+        // Not for every site under a public suffix (RFC 6265 5.3), unless it is the host
+        // itself, which only gets it back
+        if !cookie.host_only && is_public_suffix(&cookie.domain) {
+            match url_host(url) == Some(cookie.domain.clone()) {
+                true => cookie.host_only = true,
+                false => {
+                    log::debug!("Ignoring cookie for {} from {}", cookie.domain, url);
+                    return;
+                }
+            }
+        }
+
+        let mut cookies = lock(&self.cookies);
+        // Sent again as it is, e.g.: on every response
+        if cookies.cookies.contains(&cookie) {
+            return;
+        }
+
+        let now = now();
+        cookies
+            .cookies
+            .retain(|c| !c.same_key(&cookie) && !c.is_expired(now));
+        if !cookie.is_expired(now) {
+            cookies.cookies.push(cookie);
+        }
+        cookies.dirty = true;
+    }
+
+    pub fn clear(&self) -> Result<(), EbiError> {
+        let _saving = lock(&self.saving);
+        let mut cookies = lock(&self.cookies);
+        cookies.cookies.clear();
+        cookies.dirty = false;
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    // Writes pending changes. Cookies can't be saved without a credential key, e.g.:
+    // without a keyring until a passphrase is set: they are kept in memory, and written
+    // by a later flush
+    pub fn flush(&self) -> Result<(), EbiError> {
+        let _saving = lock(&self.saving);
+        let snapshot = {
+            let mut cookies = lock(&self.cookies);
+            if !cookies.dirty {
+                return Ok(());
+            }
+            cookies.dirty = false;
+            cookies.cookies.clone()
+        };
+
+        let result = self.write(&snapshot);
+        let mut cookies = lock(&self.cookies);
+        match result {
+            Ok(()) => cookies.save_error = None,
+            Err(ref e) => {
+                if cookies.save_error.is_none() {
+                    log::warn!("Could not save cookies at {} :: {}", self.path.display(), e);
+                }
+                cookies.dirty = true;
+                cookies.save_error = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    // Why the cookies could not be written last time, None once they are on disk
+    pub fn save_error(&self) -> Option<String> {
+        lock(&self.cookies).save_error.clone()
+    }
+
+    fn write(&self, cookies: &[Cookie]) -> Result<(), EbiError> {
+        let content =
+            serde_json::to_vec(cookies).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))?;
+        let aad = format!("cookies:{}", self.identifier);
+        self.credentials.write_sealed(&self.path, &aad, content)
+    }
+}
+
+impl Drop for CookieStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+// Callbacks given to the source. `store` is the CookieStore kept alive by the loaded Source
+impl CookieStore {
+    pub fn abi_cookie_jar(&self) -> ABICookieJar {
+        ABICookieJar {
+            store: self as *const CookieStore as *const c_void,
+            cookie_header: abi_cookie_header,
+            set_cookie: abi_set_cookie,
+        }
+    }
+}
+
+extern "C" fn abi_cookie_header(store: *const c_void, url: FFIString) -> FFIString {
+    let store = unsafe { &*(store as *const CookieStore) };
+    let url: String = match url.try_into() {
+        Ok(url) => url,
+        Err(_) => return FFIString::null(),
+    };
+
+    match store.cookie_header(&url) {
+        Some(header) => FFIString::from(header),
+        None => FFIString::null(),
+    }
+}
+
+extern "C" fn abi_set_cookie(store: *const c_void, url: FFIString, set_cookie: FFIString) {
+    let store = unsafe { &*(store as *const CookieStore) };
+    let url: Result<String, _> = url.try_into();
+    let set_cookie: Result<String, _> = set_cookie.try_into();
+    if let (Ok(url), Ok(set_cookie)) = (url, set_cookie) {
+        store.set_cookie(&url, &set_cookie);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::CredentialKey;
+
+    const URL: &str = "https://example.com/login";
+
+    fn stores(dir: &Path) -> CookieStores {
+        let key = CredentialKey::Passphrase("cookies".to_owned());
+        let credentials = Arc::new(CredentialStore::new(dir, Some(key)));
+        CookieStores::new(dir.to_path_buf(), credentials)
+    }
+
+    #[test]
+    fn cookies_are_stored_encrypted_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = stores(dir.path());
+        let store = saved.get("example").unwrap();
+        store.set_cookie(URL, "session=secret-token; Max-Age=3600");
+        // Written by the saver, or a flush
+        assert!(!cookies_path(dir.path(), "example").exists());
+        store.flush().unwrap();
+
+        let stored = std::fs::read(cookies_path(dir.path(), "example")).unwrap();
+        assert!(!String::from_utf8_lossy(&stored).contains("secret-token"));
+
+        let store = stores(dir.path()).get("example").unwrap();
+        assert_eq!(
+            store.cookie_header(URL),
+            Some("session=secret-token".to_owned())
+        );
+    }
+
+    #[test]
+    fn pending_changes_are_written_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let stores = stores(dir.path());
+            let store = stores.get("example").unwrap();
+            store.set_cookie(URL, "a=1");
+            store.set_cookie(URL, "b=2");
+        }
+
+        let store = stores(dir.path()).get("example").unwrap();
+        assert_eq!(store.cookie_header(URL), Some("a=1; b=2".to_owned()));
+    }
+
+    #[test]
+    fn rejects_public_suffix_domains() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = stores(dir.path()).get("example").unwrap();
+        store.set_cookie("https://www.example.com/", "a=1; Domain=com");
+        store.set_cookie("https://www.example.co.uk/", "b=2; Domain=co.uk");
+        store.set_cookie("https://user.github.io/", "c=3; Domain=github.io");
+        assert!(store.cookies().is_empty());
+
+        // Set by the suffix itself, kept host-only
+        store.set_cookie("https://github.io/", "d=4; Domain=github.io");
+        let cookies = store.cookies();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].domain, "github.io");
+        assert!(cookies[0].host_only);
+        assert!(!cookies[0].matches("https://user.github.io/"));
+    }
+
+    #[test]
+    fn write_failures_are_reported_and_cookies_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let credentials = Arc::new(CredentialStore::new(dir.path(), None));
+        let stores = CookieStores::new(dir.path().to_path_buf(), credentials);
+        let store = stores.get("example").unwrap();
+
+        store.set_cookie(URL, "a=1");
+        assert!(matches!(store.flush(), Err(EbiError::CredentialStore(_))));
+        assert!(store.save_error().is_some());
+        assert_eq!(store.cookie_header(URL), Some("a=1".to_owned()));
+    }
+
+    #[test]
+    fn identifiers_are_validated() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            stores(dir.path()).get("../credentials"),
+            Err(EbiError::InvalidIdentifier(_))
+        ));
+    }
+}
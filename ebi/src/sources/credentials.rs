@@ -0,0 +1,318 @@
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use ebi_source::credentials::Credentials;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::error::EbiError;
+
+use super::{lock, validate_identifier};
+
+const SALT_FILE_NAME: &str = "credentials.salt";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "ebi";
+
+// Where the key encrypting stored credentials (and cookies) comes from. It is never
+// written next to them, so copies of the config directory (backups, synced folders)
+// don't give away the credentials. It does not protect them from programs running as
+// the same user while the key is unlocked, nor from the sources, which get them at login
+#[derive(Clone)]
+pub enum CredentialKey {
+    // Random key kept in the OS keyring (Keychain, Credential Manager, Secret Service),
+    // one per config directory
+    #[cfg(feature = "keyring")]
+    Keyring,
+    // Derived from a secret the user enters (PBKDF2-HMAC-SHA256), with a random salt
+    // stored in <config_dir>/credentials.salt
+    Passphrase(String),
+}
+
+impl std::fmt::Debug for CredentialKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "keyring")]
+            Self::Keyring => write!(f, "Keyring"),
+            Self::Passphrase(_) => write!(f, "Passphrase(..)"),
+        }
+    }
+}
+
+// Keyring when available, otherwise credentials can't be stored until a passphrase is set
+pub fn default_credential_key() -> Option<CredentialKey> {
+    #[cfg(feature = "keyring")]
+    return Some(CredentialKey::Keyring);
+    #[cfg(not(feature = "keyring"))]
+    None
+}
+
+fn store_error<E: std::fmt::Display>(e: E) -> EbiError {
+    EbiError::CredentialStore(e.to_string())
+}
+
+// Written (and only readable by the current user) on first use
+pub(crate) fn write_private_file(path: &Path, content: &[u8]) -> Result<(), EbiError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path).map_err(store_error)?;
+    std::io::Write::write_all(&mut file, content).map_err(store_error)
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, EbiError> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new().fill(&mut bytes).map_err(store_error)?;
+    Ok(bytes)
+}
+
+fn cipher(key: &[u8]) -> Result<LessSafeKey, EbiError> {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, key).map_err(store_error)?;
+    Ok(LessSafeKey::new(key))
+}
+
+// nonce || ciphertext || tag, authenticated with `aad`
+fn seal_with(key: &[u8], aad: &str, content: Vec<u8>) -> Result<Vec<u8>, EbiError> {
+    let nonce = random_bytes(NONCE_LEN)?;
+    let mut sealed = content;
+    cipher(key)?
+        .seal_in_place_append_tag(
+            Nonce::try_assume_unique_for_key(&nonce).map_err(store_error)?,
+            Aad::from(aad.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(store_error)?;
+
+    let mut content = nonce;
+    content.extend(sealed);
+    Ok(content)
+}
+
+fn open_with(key: &[u8], aad: &str, mut content: Vec<u8>) -> Result<Vec<u8>, EbiError> {
+    if content.len() < NONCE_LEN {
+        return Err(EbiError::CredentialStore(aad.to_owned()));
+    }
+    let mut sealed = content.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&content).map_err(store_error)?;
+
+    let opened = cipher(key)?
+        .open_in_place(nonce, Aad::from(aad.as_bytes()), &mut sealed)
+        .map_err(|_| EbiError::CredentialStore(aad.to_owned()))?;
+    Ok(opened.to_vec())
+}
+
+// Encrypted credentials in <config_dir>/credentials/<identifier>.bin. The key is resolved
+// on first use and kept in memory
+pub struct CredentialStore {
+    config_dir: PathBuf,
+    key: Mutex<Option<CredentialKey>>,
+    resolved: Mutex<Option<Vec<u8>>>,
+}
+
+impl CredentialStore {
+    pub fn new(config_dir: &Path, key: Option<CredentialKey>) -> Self {
+        Self {
+            config_dir: config_dir.to_path_buf(),
+            key: Mutex::new(key),
+            resolved: Mutex::new(None),
+        }
+    }
+
+    // Credentials stored with a previous key can't be read with the new one
+    pub fn set_key(&self, key: Option<CredentialKey>) {
+        let mut resolved = lock(&self.resolved);
+        *lock(&self.key) = key;
+        *resolved = None;
+    }
+
+    // <config_dir>/credentials/<identifier>.bin
+    fn path(&self, identifier: &str) -> Result<PathBuf, EbiError> {
+        validate_identifier(identifier)?;
+        let mut path = self.config_dir.clone();
+        path.push("credentials");
+        path.push(format!("{}.bin", identifier));
+        Ok(path)
+    }
+
+    // Authenticated with the identifier, so files can't be swapped between sources
+    pub fn write(&self, identifier: &str, credentials: &Credentials) -> Result<(), EbiError> {
+        let path = self.path(identifier)?;
+        let content = serde_json::to_vec(credentials).map_err(store_error)?;
+        self.write_sealed(&path, identifier, content)
+    }
+
+    pub fn read(&self, identifier: &str) -> Result<Option<Credentials>, EbiError> {
+        let path = self.path(identifier)?;
+        match self.read_sealed(&path, identifier)? {
+            Some(credentials) => serde_json::from_slice(&credentials)
+                .map(Some)
+                .map_err(store_error),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove(&self, identifier: &str) -> Result<(), EbiError> {
+        let path = self.path(identifier)?;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    // Encrypted with the store key, authenticated with `aad`
+    pub(crate) fn write_sealed(
+        &self,
+        path: &Path,
+        aad: &str,
+        content: Vec<u8>,
+    ) -> Result<(), EbiError> {
+        let sealed = seal_with(&self.resolve_key()?, aad, content)?;
+        write_private_file(path, &sealed)
+    }
+
+    // None when there is no file
+    pub(crate) fn read_sealed(&self, path: &Path, aad: &str) -> Result<Option<Vec<u8>>, EbiError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        // Resolved first, as it may migrate the file
+        let key = self.resolve_key()?;
+        open_with(&key, aad, std::fs::read(path)?).map(Some)
+    }
+
+    fn resolve_key(&self) -> Result<Vec<u8>, EbiError> {
+        let mut resolved = lock(&self.resolved);
+        if let Some(ref key) = *resolved {
+            return Ok(key.clone());
+        }
+
+        let key = match *lock(&self.key) {
+            #[cfg(feature = "keyring")]
+            Some(CredentialKey::Keyring) => self.keyring_key()?,
+            Some(CredentialKey::Passphrase(ref passphrase)) => self.passphrase_key(passphrase)?,
+            None => {
+                return Err(EbiError::CredentialStore(
+                    "no credential key configured".to_owned(),
+                ))
+            }
+        };
+
+        *resolved = Some(key.clone());
+        Ok(key)
+    }
+
+    #[cfg(feature = "keyring")]
+    fn keyring_key(&self) -> Result<Vec<u8>, EbiError> {
+        let user = format!("credentials-key:{}", self.config_dir.display());
+        let entry = keyring::Entry::new(KEYRING_SERVICE, &user).map_err(store_error)?;
+
+        match entry.get_password() {
+            Ok(key) => hex::decode(key).map_err(store_error),
+            Err(keyring::Error::NoEntry) => {
+                let key = random_bytes(KEY_LEN)?;
+                entry
+                    .set_password(&hex::encode(&key))
+                    .map_err(store_error)?;
+                Ok(key)
+            }
+            Err(e) => Err(store_error(e)),
+        }
+    }
+
+    fn passphrase_key(&self, passphrase: &str) -> Result<Vec<u8>, EbiError> {
+        let mut path = self.config_dir.clone();
+        path.push(SALT_FILE_NAME);
+        let salt = match path.exists() {
+            true => std::fs::read(&path)?,
+            false => {
+                let salt = random_bytes(SALT_LEN)?;
+                write_private_file(&path, &salt)?;
+                salt
+            }
+        };
+
+        let mut key = vec![0u8; KEY_LEN];
+        let iterations = NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(); // safe to unwrap
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> Credentials {
+        Credentials::Password {
+            username: "user".to_owned(),
+            password: "secret".to_owned(),
+        }
+    }
+
+    fn store(dir: &Path, passphrase: &str) -> CredentialStore {
+        CredentialStore::new(dir, Some(CredentialKey::Passphrase(passphrase.to_owned())))
+    }
+
+    #[test]
+    fn credentials_round_trip_with_a_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path(), "master password");
+        store.write("example", &credentials()).unwrap();
+
+        let stored = std::fs::read(dir.path().join("credentials/example.bin")).unwrap();
+        assert!(!String::from_utf8_lossy(&stored).contains("secret"));
+        assert_eq!(store.read("example").unwrap(), Some(credentials()));
+
+        store.remove("example").unwrap();
+        assert_eq!(store.read("example").unwrap(), None);
+    }
+
+    #[test]
+    fn another_passphrase_or_source_can_not_read_them() {
+        let dir = tempfile::tempdir().unwrap();
+        store(dir.path(), "master password")
+            .write("example", &credentials())
+            .unwrap();
+
+        assert!(store(dir.path(), "guess").read("example").is_err());
+
+        let credentials_dir = dir.path().join("credentials");
+        std::fs::copy(
+            credentials_dir.join("example.bin"),
+            credentials_dir.join("other.bin"),
+        )
+        .unwrap();
+        assert!(store(dir.path(), "master password").read("other").is_err());
+    }
+
+    #[test]
+    fn stores_without_a_key_fail_and_identifiers_are_validated() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(dir.path(), None);
+        assert!(matches!(
+            store.write("example", &credentials()),
+            Err(EbiError::CredentialStore(_))
+        ));
+        assert!(matches!(
+            store.read("../example"),
+            Err(EbiError::InvalidIdentifier(_))
+        ));
+    }
+}
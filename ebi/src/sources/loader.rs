@@ -1,46 +1,416 @@
-use std::path::PathBuf;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use ebi_source::{
     abi::{
         chapter::{
-            chapter_list::{ABIChapterListInput, ChapterListFn},
-            chapter_page_list::{ABIChapterPageListInput, ChapterPageListFn},
+            chapter_list::{ABIChapterListInput, ChapterListAsyncFn, ChapterListFn},
+            chapter_page_list::{
+                ABIChapterPageListInput, ChapterPageListAsyncFn, ChapterPageListFn,
+            },
+        },
+        cookies::SetCookieJarFn,
+        lifecycle::{ABIStateHandle, InitFn, ShutdownFn},
+        login::{LoginFn, LogoutFn},
+        manga::{
+            manga_list::{MangaListAsyncFn, MangaListFn},
+            search::{SearchAsyncFn, SearchFn},
         },
-        manga::manga_list::MangaListFn,
+        preferences::PreferencesSchemaFn,
+        primitives::FFIString,
         source::source_info::SourceInfoFn,
+        AbiVersionFn,
     },
+    credentials::Credentials,
     error::SourceError,
+    preferences::{PreferenceSchema, Preferences},
 };
 use libloading::Library;
+use sha2::{Digest, Sha256};
 
 use crate::error::EbiError;
 
-use super::{EbiChapter, EbiManga, EbiSource, SourceLoader};
+use super::cookies::CookieStore;
+use super::manager::handle_source_file_extension;
+use super::{EbiChapter, EbiManga, EbiSource, Locale, SourceLoader};
+
+const ABI_VERSION_SYMBOL: &str = "abi_version";
+const INIT_SYMBOL: &str = "abi_init";
+const SHUTDOWN_SYMBOL: &str = "abi_shutdown";
+const PREFERENCES_SCHEMA_SYMBOL: &str = "abi_preferences_schema";
+const LOGIN_SYMBOL: &str = "abi_login";
+const LOGOUT_SYMBOL: &str = "abi_logout";
+const SET_COOKIE_JAR_SYMBOL: &str = "abi_set_cookie_jar";
+const SOURCE_INFO_SYMBOL: &str = "abi_source_info";
+const MANGA_LIST_SYMBOL: &str = "abi_manga_list";
+const SEARCH_SYMBOL: &str = "abi_search";
+const CHAPTER_LIST_SYMBOL: &str = "abi_chapter_list";
+const CHAPTER_PAGE_LIST_SYMBOL: &str = "abi_chapter_page_list";
+const MANGA_LIST_ASYNC_SYMBOL: &str = "abi_manga_list_async";
+const SEARCH_ASYNC_SYMBOL: &str = "abi_search_async";
+const CHAPTER_LIST_ASYNC_SYMBOL: &str = "abi_chapter_list_async";
+const CHAPTER_PAGE_LIST_ASYNC_SYMBOL: &str = "abi_chapter_page_list_async";
+
+const LIBRARY_COPY_DIR_NAME: &str = "libs";
+// Hex digits of the library hash kept in the name of its copy
+const LIBRARY_COPY_HASH_LEN: usize = 16;
+
+// Optional functions a source may not export
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceFunctions {
+    pub search: bool,
+    pub chapter_list: bool,
+    pub chapter_page_list: bool,
+    // Needs (or supports) an account
+    pub login: bool,
+    // Written with `async fn`, so its calls can be polled by the host's executor
+    pub asynchronous: bool,
+}
+
+// Resolved once at load. The pointers are only valid while `Source::lib` is loaded
+struct SourceVTable {
+    shutdown: Option<ShutdownFn>,
+    manga_list: MangaListFn,
+    search: Option<SearchFn>,
+    chapter_list: Option<ChapterListFn>,
+    chapter_page_list: Option<ChapterPageListFn>,
+    login: Option<LoginFn>,
+    logout: Option<LogoutFn>,
+    set_cookie_jar: Option<SetCookieJarFn>,
+    // Only exported by sources written with `async fn`
+    manga_list_async: Option<MangaListAsyncFn>,
+    search_async: Option<SearchAsyncFn>,
+    chapter_list_async: Option<ChapterListAsyncFn>,
+    chapter_page_list_async: Option<ChapterPageListAsyncFn>,
+}
+
+impl SourceVTable {
+    fn load(lib: &Library) -> Result<Self, EbiError> {
+        Ok(Self {
+            shutdown: load_optional_symbol(lib, SHUTDOWN_SYMBOL),
+            manga_list: load_symbol(lib, MANGA_LIST_SYMBOL)?,
+            search: load_optional_symbol(lib, SEARCH_SYMBOL),
+            chapter_list: load_optional_symbol(lib, CHAPTER_LIST_SYMBOL),
+            chapter_page_list: load_optional_symbol(lib, CHAPTER_PAGE_LIST_SYMBOL),
+            login: load_optional_symbol(lib, LOGIN_SYMBOL),
+            logout: load_optional_symbol(lib, LOGOUT_SYMBOL),
+            set_cookie_jar: load_optional_symbol(lib, SET_COOKIE_JAR_SYMBOL),
+            manga_list_async: load_optional_symbol(lib, MANGA_LIST_ASYNC_SYMBOL),
+            search_async: load_optional_symbol(lib, SEARCH_ASYNC_SYMBOL),
+            chapter_list_async: load_optional_symbol(lib, CHAPTER_LIST_ASYNC_SYMBOL),
+            chapter_page_list_async: load_optional_symbol(lib, CHAPTER_PAGE_LIST_ASYNC_SYMBOL),
+        })
+    }
+
+    fn functions(&self) -> SourceFunctions {
+        SourceFunctions {
+            search: self.search.is_some(),
+            chapter_list: self.chapter_list.is_some(),
+            chapter_page_list: self.chapter_page_list.is_some(),
+            login: self.login.is_some(),
+            asynchronous: self.manga_list_async.is_some()
+                || self.search_async.is_some()
+                || self.chapter_list_async.is_some()
+                || self.chapter_page_list_async.is_some(),
+        }
+    }
+}
+
+fn load_symbol<T: Copy>(lib: &Library, symbol: &str) -> Result<T, EbiError> {
+    let loaded = unsafe { lib.get::<T>(symbol.as_bytes()) };
+    loaded
+        .map(|loaded| *loaded)
+        .map_err(|_| EbiError::LoadFunction(symbol.to_owned()))
+}
+
+fn load_optional_symbol<T: Copy>(lib: &Library, symbol: &str) -> Option<T> {
+    let loaded = load_symbol(lib, symbol).ok();
+    if loaded.is_none() {
+        log::debug!("Source does not export {}", symbol);
+    }
+    loaded
+}
+
+// Sources are loaded from a private copy of their verified bytes, named after their hash
+// (<cache_dir>/libs/lib<identifier>-<hash>.so). The installed file can't be swapped
+// between verification and loading, and a changed library gets a path of its own, so the
+// loader never hands back the one still mapped for an older Source
+pub(crate) fn library_copy(
+    cache_dir: &Path,
+    identifier: &str,
+    lib: &[u8],
+) -> Result<PathBuf, EbiError> {
+    let mut dir = cache_dir.to_path_buf();
+    dir.push(LIBRARY_COPY_DIR_NAME);
+    create_private_dir(&dir)?;
+
+    let hash = hex::encode(Sha256::digest(lib));
+    let copy_name = format!("{}-{}", identifier, &hash[..LIBRARY_COPY_HASH_LEN]);
+    let mut path = dir.clone();
+    path.push(handle_source_file_extension(&copy_name));
+
+    let is_copy = |path: &Path| {
+        std::fs::read(path).is_ok_and(|copy| hex::encode(Sha256::digest(copy)) == hash)
+    };
+    if !is_copy(&path) {
+        let mut tmp_path = path.clone();
+        tmp_path.set_extension("tmp");
+        std::fs::write(&tmp_path, lib).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))?;
+        std::fs::rename(&tmp_path, &path).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))?;
+    }
+
+    remove_library_copies(&dir, identifier, &path);
+    Ok(path)
+}
+
+// Older copies of the source. Copies still mapped may fail to be removed (e.g.: on
+// Windows), they are retried on the next load
+fn remove_library_copies(dir: &Path, identifier: &str, keep: &Path) {
+    let template = handle_source_file_extension(&format!("{}-*", identifier));
+    let (prefix, suffix) = match template.to_str().and_then(|name| name.split_once('*')) {
+        Some(parts) => parts,
+        None => return,
+    };
+    let is_copy = |name: &str| {
+        name.strip_prefix(prefix)
+            .and_then(|name| name.strip_suffix(suffix))
+            .is_some_and(|hash| {
+                hash.len() == LIBRARY_COPY_HASH_LEN && hash.chars().all(|c| c.is_ascii_hexdigit())
+            })
+    };
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let name = path.file_name().and_then(|name| name.to_str());
+        if path != keep && name.is_some_and(is_copy) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::debug!("Could not remove {} :: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<(), EbiError> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| EbiError::InvalidDir(format!("{} :: {}", dir.display(), e)))
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> Result<(), EbiError> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| EbiError::InvalidDir(format!("{} :: {}", dir.display(), e)))
+}
 
 pub struct Source {
-    lib: Library,
+    vtable: SourceVTable,
+    // Created by abi_init at load, released by abi_shutdown before the library is unloaded
+    state: ABIStateHandle,
     source: EbiSource,
+    preferences_schema: PreferenceSchema,
+    // Given to the source by pointer, so it must outlive abi_shutdown
+    cookies: Option<Arc<CookieStore>>,
+    // Dropped last, so the library outlives the resolved pointers
+    _lib: Library,
 }
 
-impl std::convert::TryFrom<PathBuf> for Source {
-    type Error = EbiError;
-
-    fn try_from(source_path: PathBuf) -> Result<Self, Self::Error> {
+impl Source {
+    // `identifier` is the one the source is installed as, used for errors raised before
+    // source_info answered. `preferences` are the values stored for the source, given to
+    // abi_init
+    pub fn load(
+        identifier: &str,
+        source_path: PathBuf,
+        preferences: &Preferences,
+    ) -> Result<Self, EbiError> {
         let lib = unsafe { Library::new(source_path.clone()).map_err(|_| EbiError::LoadLib)? };
         log::debug!("Loaded Source from {}", source_path.display());
 
-        let source_fn = unsafe {
-            lib.get::<SourceInfoFn>(b"abi_source_info")
-                .map_err(|_| EbiError::LoadFunction)?
+        // Checked before calling into the source, as a mismatch means undefined behavior
+        match load_optional_symbol::<AbiVersionFn>(&lib, ABI_VERSION_SYMBOL) {
+            Some(abi_version) if !ebi_source::abi::is_compatible(abi_version()) => {
+                return Err(EbiError::IncompatibleAbiVersion(abi_version()));
+            }
+            Some(_) => {}
+            None => log::warn!("Source {} has no ABI version", source_path.display()),
+        }
+
+        let source_fn: SourceInfoFn = load_symbol(&lib, SOURCE_INFO_SYMBOL)?;
+        let vtable = SourceVTable::load(&lib)?;
+
+        let preferences_schema =
+            match load_optional_symbol::<PreferencesSchemaFn>(&lib, PREFERENCES_SCHEMA_SYMBOL) {
+                Some(preferences_schema) => ebi_source::abi::json::decode(preferences_schema())
+                    .and_then(|schema: PreferenceSchema| schema.validate().map(|_| schema))
+                    .map_err(|e| EbiError::from_source(identifier, PREFERENCES_SCHEMA_SYMBOL, e))?,
+                None => PreferenceSchema::default(),
+            };
+
+        let state = match load_optional_symbol::<InitFn>(&lib, INIT_SYMBOL) {
+            Some(init) => {
+                let preferences = ebi_source::abi::json::encode(preferences);
+                let state: Result<ABIStateHandle, SourceError> = init(preferences).into();
+                state.map_err(|e| {
+                    log::error!("Error initializing source: {}", e);
+                    EbiError::from_source(identifier, INIT_SYMBOL, e)
+                })?
+            }
+            None => ABIStateHandle::null(),
         };
 
-        let source: Result<EbiSource, SourceError> = source_fn().into();
+        let source: Result<EbiSource, SourceError> = source_fn(state).into();
         let source = source.map_err(|e| {
             log::error!("Error loading source: {}", e);
-            EbiError::SourceError(e.to_string())
+            if let Some(shutdown) = vtable.shutdown {
+                shutdown(state);
+            }
+            EbiError::from_source(identifier, SOURCE_INFO_SYMBOL, e)
         })?;
 
-        Ok(Self { lib, source })
+        Ok(Self {
+            vtable,
+            state,
+            source,
+            preferences_schema,
+            cookies: None,
+            _lib: lib,
+        })
+    }
+}
+
+// Runs before the fields are dropped, so while the library is still loaded
+impl Drop for Source {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.vtable.shutdown {
+            shutdown(self.state);
+        }
+    }
+}
+
+impl Source {
+    pub fn functions(&self) -> SourceFunctions {
+        self.vtable.functions()
+    }
+
+    pub fn preferences_schema(&self) -> &PreferenceSchema {
+        &self.preferences_schema
+    }
+
+    // Only given once, sources keep the jar until shutdown
+    pub fn set_cookie_store(&mut self, cookies: Arc<CookieStore>) {
+        if self.cookies.is_some() {
+            return;
+        }
+
+        if let Some(set_cookie_jar) = self.vtable.set_cookie_jar {
+            set_cookie_jar(self.state, cookies.abi_cookie_jar());
+        }
+        self.cookies = Some(cookies);
+    }
+
+    pub fn login(&self, credentials: &Credentials) -> Result<(), EbiError> {
+        let login = self
+            .vtable
+            .login
+            .ok_or_else(|| EbiError::UnsupportedFunction(LOGIN_SYMBOL.to_owned()))?;
+
+        let credentials = ebi_source::abi::json::encode(credentials);
+        let login: Result<(), SourceError> = login(self.state, credentials).into();
+        login.map_err(|e| {
+            log::error!("Error login: {}", e);
+            EbiError::from_source(&self.source.identifier, LOGIN_SYMBOL, e)
+        })
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<EbiManga>, EbiError> {
+        let search = self
+            .vtable
+            .search
+            .ok_or_else(|| EbiError::UnsupportedFunction(SEARCH_SYMBOL.to_owned()))?;
+
+        let query = FFIString::from(query.to_owned());
+        self.map_result(SEARCH_SYMBOL, search(self.state, query).into())
+    }
+
+    // Sources without abi_logout only keep their session in memory, dropped on unload
+    pub fn logout(&self) -> Result<(), EbiError> {
+        let logout = match self.vtable.logout {
+            Some(logout) => logout,
+            None => return Ok(()),
+        };
+
+        let logout: Result<(), SourceError> = logout(self.state).into();
+        logout.map_err(|e| {
+            log::error!("Error logout: {}", e);
+            EbiError::from_source(&self.source.identifier, LOGOUT_SYMBOL, e)
+        })
+    }
+
+    fn map_result<T>(
+        &self,
+        operation: &'static str,
+        result: Result<Vec<T>, SourceError>,
+    ) -> Result<Vec<T>, EbiError> {
+        result.map_err(|e| {
+            log::error!("Error {}: {}", operation, e);
+            EbiError::from_source(&self.source.identifier, operation, e)
+        })
+    }
+}
+
+// Async calls. None when the source only exports blocking functions
+#[cfg(feature = "async")]
+impl Source {
+    pub fn manga_list_future(
+        &self,
+    ) -> Option<impl Future<Output = Result<Vec<EbiManga>, EbiError>> + Send + '_> {
+        let manga_list = self.vtable.manga_list_async?;
+        let future = manga_list(self.state);
+
+        Some(async move { self.map_result(MANGA_LIST_ASYNC_SYMBOL, future.await.into()) })
+    }
+
+    pub fn search_future(
+        &self,
+        query: &str,
+    ) -> Option<impl Future<Output = Result<Vec<EbiManga>, EbiError>> + Send + '_> {
+        let search = self.vtable.search_async?;
+        let future = search(self.state, FFIString::from(query.to_owned()));
+
+        Some(async move { self.map_result(SEARCH_ASYNC_SYMBOL, future.await.into()) })
+    }
+
+    pub fn chapter_list_future(
+        &self,
+        manga: &EbiManga,
+        languages: &[Locale],
+    ) -> Option<impl Future<Output = Result<Vec<EbiChapter>, EbiError>> + Send + '_> {
+        let chapter_list = self.vtable.chapter_list_async?;
+        let future = chapter_list(self.state, ABIChapterListInput::new(manga, languages));
+
+        Some(async move { self.map_result(CHAPTER_LIST_ASYNC_SYMBOL, future.await.into()) })
+    }
+
+    pub fn chapter_page_list_future(
+        &self,
+        chapter: &EbiChapter,
+    ) -> Option<impl Future<Output = Result<Vec<String>, EbiError>> + Send + '_> {
+        let chapter_page_list = self.vtable.chapter_page_list_async?;
+        let future = chapter_page_list(self.state, ABIChapterPageListInput::from(chapter));
+
+        Some(async move { self.map_result(CHAPTER_PAGE_LIST_ASYNC_SYMBOL, future.await.into()) })
     }
 }
 
@@ -52,50 +422,70 @@ impl SourceLoader for Source {
     }
 
     fn manga_list(&self) -> Result<Vec<EbiManga>, Self::Error> {
-        let manga_list = unsafe {
-            self.lib
-                .get::<MangaListFn>(b"abi_manga_list")
-                .map_err(|_| EbiError::LoadFunction)?
-        };
-        let manga_list: Result<Vec<EbiManga>, SourceError> = manga_list().into();
-        let manga_list = manga_list.map_err(|e| {
-            log::error!("Error manga list: {}", e);
-            EbiError::SourceError(e.to_string())
-        })?;
-
-        Ok(manga_list)
+        self.map_result(
+            MANGA_LIST_SYMBOL,
+            (self.vtable.manga_list)(self.state).into(),
+        )
     }
 
-    fn chapter_list(&self, manga: &EbiManga) -> Result<Vec<EbiChapter>, Self::Error> {
-        let manga = ABIChapterListInput::from(manga);
-
-        let chapter_list = unsafe {
-            self.lib
-                .get::<ChapterListFn>(b"abi_chapter_list")
-                .map_err(|_| EbiError::LoadFunction)?
-        };
-        let chapter_list: Result<Vec<EbiChapter>, SourceError> = chapter_list(manga).into();
-        let chapter_list = chapter_list.map_err(|e| {
-            log::error!("Error chapter list: {}", e);
-            EbiError::SourceError(e.to_string())
-        })?;
+    fn chapter_list(
+        &self,
+        manga: &EbiManga,
+        languages: &[Locale],
+    ) -> Result<Vec<EbiChapter>, Self::Error> {
+        let chapter_list = self
+            .vtable
+            .chapter_list
+            .ok_or_else(|| EbiError::UnsupportedFunction(CHAPTER_LIST_SYMBOL.to_owned()))?;
 
-        Ok(chapter_list)
+        let manga = ABIChapterListInput::new(manga, languages);
+        self.map_result(CHAPTER_LIST_SYMBOL, chapter_list(self.state, manga).into())
     }
 
     fn chapter_page_list(&self, chapter: &EbiChapter) -> Result<Vec<String>, Self::Error> {
+        let chapter_page_list = self
+            .vtable
+            .chapter_page_list
+            .ok_or_else(|| EbiError::UnsupportedFunction(CHAPTER_PAGE_LIST_SYMBOL.to_owned()))?;
+
         let chapter = ABIChapterPageListInput::from(chapter);
-        let chapter_page_list = unsafe {
-            self.lib
-                .get::<ChapterPageListFn>(b"abi_chapter_page_list")
-                .map_err(|_| EbiError::LoadFunction)?
-        };
-        let chapter_page_list: Result<Vec<String>, SourceError> = chapter_page_list(chapter).into();
-        let chapter_page_list = chapter_page_list.map_err(|e| {
-            log::error!("Error chapter page list: {}", e);
-            EbiError::SourceError(e.to_string())
-        })?;
+        self.map_result(
+            CHAPTER_PAGE_LIST_SYMBOL,
+            chapter_page_list(self.state, chapter).into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_copies_are_named_after_their_content() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let first = library_copy(cache_dir.path(), "example", b"first").unwrap();
+        assert_eq!(
+            first,
+            library_copy(cache_dir.path(), "example", b"first").unwrap()
+        );
+        assert_eq!(std::fs::read(&first).unwrap(), b"first");
+
+        let second = library_copy(cache_dir.path(), "example", b"second").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(std::fs::read(&second).unwrap(), b"second");
+    }
+
+    #[test]
+    fn older_copies_of_the_same_source_are_removed() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let other = library_copy(cache_dir.path(), "example-other", b"other").unwrap();
+        let first = library_copy(cache_dir.path(), "example", b"first").unwrap();
+        let second = library_copy(cache_dir.path(), "example", b"second").unwrap();
 
-        Ok(chapter_page_list)
+        assert!(!first.exists());
+        assert!(second.exists());
+        assert!(other.exists());
     }
 }
@@ -1,45 +1,178 @@
-use ebi_source::SourceLoader;
-use std::{collections::HashMap, path::PathBuf};
+use ebi_source::{manifest::MANIFEST_FILE_NAME, SourceLoader};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    time::Duration,
+};
 
+use crate::config::{CacheConfig, EbiConfig, HttpConfig, SignaturePolicy};
+use crate::downloader::{fetch, HttpClient};
 use crate::error::EbiError;
 
-use super::loader::Source;
-use super::{EbiChapter, EbiManga, EbiSource};
+use super::archive::{ListedChapter, SourceArchiver};
+use super::builder::SourceManagerBuilder;
+use super::cache::{CacheKey, CacheOperation, MetadataCache};
+use super::cookies::{CookieStore, CookieStores};
+use super::credentials::{CredentialKey, CredentialStore};
+use super::loader::{library_copy, Source};
+use super::manifest::{read_manifest, validate_manifest};
+use super::migration::{Migration, MigrationCandidate};
+use super::preferences::{read_preferences, remove_preferences, write_preferences};
+use super::report::{LoadOutcome, LoadReport, ManifestReport};
+use super::repository::{InstalledSource, RepositoryArtifact, RepositoryIndex, RepositorySource};
+use super::search::{GlobalSearch, SearchPool, SourceSearchResult};
+use super::signature::{signature_path, validate_public_key, verify_source, PUBLIC_KEY_FILE_NAME};
+#[cfg(feature = "watcher")]
+use super::watcher::SourceWatcher;
+use super::{
+    validate_identifier, Cookie, Credentials, EbiChapter, EbiManga, EbiSource, Locale,
+    PreferenceSchema, PreferenceValue, Preferences, SourceFunctions, SourceManifest,
+};
+
+// <source_dir>/.staging/<identifier>, where installs are checked before replacing the
+// installed source. Identifiers never start with a dot
+const STAGING_DIR_NAME: &str = ".staging";
 
 #[cfg(target_os = "macos")]
-fn handle_source_file_extension(identifier: &str) -> PathBuf {
+pub(crate) fn handle_source_file_extension(identifier: &str) -> PathBuf {
     let file = format!("lib{}.dylib", identifier);
     PathBuf::from(file)
 }
 
 #[cfg(target_os = "windows")]
-fn handle_source_file_extension(identifier: &str) -> PathBuf {
+pub(crate) fn handle_source_file_extension(identifier: &str) -> PathBuf {
     let file = format!("{}.dll", identifier);
     PathBuf::from(file)
 }
 
 #[cfg(target_os = "linux")]
-fn handle_source_file_extension(identifier: &str) -> PathBuf {
+pub(crate) fn handle_source_file_extension(identifier: &str) -> PathBuf {
     let file = format!("lib{}.so", identifier);
     PathBuf::from(file)
 }
 
+// The source directory is named after the source identifier
+fn source_identifier(dir: &Path) -> Result<&str, EbiError> {
+    dir.file_name()
+        .and_then(|f| f.to_str())
+        .ok_or_else(|| EbiError::InvalidDir(dir.to_string_lossy().into_owned()))
+}
+
+// <dir>/lib<identifier>.so
+fn source_lib_path(dir: &Path, identifier: &str) -> PathBuf {
+    let mut file_path = dir.to_path_buf();
+    file_path.push(handle_source_file_extension(identifier));
+    file_path
+}
+
+// Sources may ignore the preferred languages. Chapters without a language are kept,
+// as they may be in any of them
+pub(crate) fn filter_chapters(chapters: Vec<EbiChapter>, languages: &[Locale]) -> Vec<EbiChapter> {
+    if languages.is_empty() {
+        return chapters;
+    }
+
+    chapters
+        .into_iter()
+        .filter(|chapter| {
+            chapter.language.is_unknown()
+                || languages
+                    .iter()
+                    .any(|language| chapter.language.matches(language))
+        })
+        .collect()
+}
+
+// See SourceManager::cache_response_with_age, for calls made off the manager, e.g.: by
+// the search pool
+fn cache_response<T>(
+    cache: &MetadataCache,
+    enabled: bool,
+    key: &CacheKey,
+    result: Result<T, EbiError>,
+) -> Result<(T, Option<Duration>), EbiError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    if !enabled {
+        return result.map(|value| (value, None));
+    }
+
+    match result {
+        Ok(value) => {
+            cache.insert(key, &value);
+            Ok((value, None))
+        }
+        Err(e) if !e.is_retryable() => Err(e),
+        Err(e) => match cache.get(key) {
+            Some((value, age)) => {
+                log::warn!(
+                    "Serving cached {} of {} :: {}",
+                    key.operation.as_str(),
+                    key.source,
+                    e
+                );
+                Ok((value, Some(age)))
+            }
+            None => Err(e),
+        },
+    }
+}
+
+// Logs back in with the stored credentials and retries once when the session expired
+fn with_session<T, F>(
+    credentials: &CredentialStore,
+    identifier: &str,
+    source: &Source,
+    call: F,
+) -> Result<T, EbiError>
+where
+    F: Fn(&Source) -> Result<T, EbiError>,
+{
+    match call(source) {
+        Err(e) if e.is_login_required() && source.functions().login => {
+            let credentials = match credentials.read(identifier)? {
+                Some(credentials) => credentials,
+                None => return Err(e),
+            };
+            log::info!("Logging back in to {}", identifier);
+            source.login(&credentials)?;
+            call(source)
+        }
+        result => result,
+    }
+}
+
 pub struct SourceManager {
-    dir_path: PathBuf,
-    sources: HashMap<String, Source>,
+    data_dir: PathBuf,
+    cache_dir: PathBuf,
+    config_dir: PathBuf,
+    config: EbiConfig,
+    // Built from config.http
+    http: HttpClient,
+    sources: HashMap<String, Arc<Source>>,
+    // Kept across reloads, so downloads in progress share the new session
+    cookie_stores: Arc<CookieStores>,
+    // Shared with the threads refreshing stale responses
+    cache: Arc<MetadataCache>,
+    credentials: Arc<CredentialStore>,
+    // Runs the calls of global searches
+    search_pool: SearchPool,
+    // See set_offline
+    offline: bool,
+    #[cfg(feature = "watcher")]
+    watcher: Option<SourceWatcher>,
 }
 
+// Kept for compatibility, prefer SourceManager::builder(). Uses $HOME/.ebi as data, cache
+// and config directory, like SourceManager::new
 #[cfg(target_family = "unix")]
 impl std::default::Default for SourceManager {
     fn default() -> Self {
         let home = std::env::var("HOME").unwrap();
-        let path = PathBuf::from(format!("{}/.ebi", home));
-        std::fs::create_dir_all(&path).unwrap();
-
-        Self {
-            dir_path: path,
-            sources: HashMap::new(),
-        }
+        Self::new(&format!("{}/.ebi", home))
     }
 }
 
@@ -47,102 +180,1285 @@ impl std::default::Default for SourceManager {
 impl SourceManager {
     pub fn sources(&self) -> Vec<EbiSource> {
         self.sources
-            .iter()
-            .map(|(_, source)| source.source_info().unwrap())
+            .values()
+            .map(|source| source.source_info().unwrap())
             .collect()
     }
 
-    pub fn manga_list(&self, source: &str) -> Result<Vec<EbiManga>, EbiError> {
+    // See Locale::matches, e.g.: "es" includes "es-419" and multi-language sources
+    pub fn sources_with_locale(&self, locale: &Locale) -> Vec<EbiSource> {
+        self.sources()
+            .into_iter()
+            .filter(|source| source.supports_locale(locale))
+            .collect()
+    }
+
+    // Optional functions exported by a loaded source
+    pub fn source_functions(&self, source: &str) -> Result<SourceFunctions, EbiError> {
         let source = self.sources.get(source).ok_or(EbiError::InvalidSource)?;
-        source.manga_list()
+        Ok(source.functions())
+    }
+
+    pub fn manga_list(&self, source: &str) -> Result<Vec<EbiManga>, EbiError> {
+        self.with_cache(&CacheKey::manga_list(source), |source| source.manga_list())
     }
 
+    pub fn search(&self, source: &str, query: &str) -> Result<Vec<EbiManga>, EbiError> {
+        self.search_with_age(source, query).map(|(manga, _)| manga)
+    }
+
+    // Age of the results when they come from the cache
+    pub(crate) fn search_with_age(
+        &self,
+        source: &str,
+        query: &str,
+    ) -> Result<(Vec<EbiManga>, Option<Duration>), EbiError> {
+        let key = CacheKey::search(source, query);
+        let query = query.to_owned();
+        self.with_cache_age(&key, move |source| source.search(&query))
+    }
+
+    // Every loaded source supporting search
+    pub fn global_search(&self, query: &str) -> GlobalSearch {
+        self.global_search_in(query, &self.search_sources())
+    }
+
+    // Sources are searched on the threads of the manager's search pool (see SearchPool),
+    // results are yielded as they come. Cached results are used and stored as by `search`
+    pub fn global_search_in(&self, query: &str, sources: &[String]) -> GlobalSearch {
+        let mut pending = sources.to_vec();
+        pending.sort();
+        pending.dedup();
+
+        let (sender, receiver) = mpsc::channel();
+        // Only cached results, which are read right away
+        if self.offline {
+            for identifier in pending.iter() {
+                let _ = sender.send(SourceSearchResult::new(
+                    identifier.clone(),
+                    self.search_with_age(identifier, query),
+                ));
+            }
+            return GlobalSearch::new(receiver, pending);
+        }
+
+        for identifier in pending.iter().cloned() {
+            // Read right away, stale results being refreshed in the background
+            let key = CacheKey::search(&identifier, query);
+            let search = {
+                let query = query.to_owned();
+                move |source: &Source| source.search(&query)
+            };
+            if let Some((manga, age)) = self.cached_with_age(&key, search.clone()) {
+                let _ = sender.send(SourceSearchResult::new(identifier, Ok((manga, Some(age)))));
+                continue;
+            }
+
+            let source = self.sources.get(&identifier).cloned();
+            let credentials = self.credentials.clone();
+            let cache = self.cache.clone();
+            let enabled = !self.config.cache.ttl(key.operation).is_zero();
+            let sender = sender.clone();
+
+            self.search_pool.execute(move || {
+                let result = match source {
+                    Some(source) => with_session(&credentials, &identifier, &source, search),
+                    None => Err(EbiError::InvalidSource),
+                };
+                let result = cache_response(&cache, enabled, &key, result);
+                // The search may have been dropped or timed out already
+                let _ = sender.send(SourceSearchResult::new(identifier, result));
+            });
+        }
+
+        GlobalSearch::new(receiver, pending)
+    }
+
+    pub(crate) fn search_sources(&self) -> Vec<String> {
+        self.sources
+            .iter()
+            .filter(|(_, source)| source.functions().search)
+            .map(|(identifier, _)| identifier.clone())
+            .collect()
+    }
+
+    // In the languages set with `set_languages`
     pub fn chapter_list(&self, manga: &EbiManga) -> Result<Vec<EbiChapter>, EbiError> {
-        let source = self
-            .sources
-            .get(&manga.source.clone())
-            .ok_or(EbiError::InvalidSource)?;
-        source.chapter_list(manga)
+        self.chapter_list_in(manga, &self.config.languages)
+    }
+
+    // Every language when `languages` is empty
+    pub fn chapter_list_in(
+        &self,
+        manga: &EbiManga,
+        languages: &[Locale],
+    ) -> Result<Vec<EbiChapter>, EbiError> {
+        if self.offline {
+            return self.offline_chapter_list(manga, languages);
+        }
+
+        let key = CacheKey::chapter_list(manga, languages);
+        let (manga, preferred) = (manga.clone(), languages.to_vec());
+        let chapters =
+            self.with_cache(&key, move |source| source.chapter_list(&manga, &preferred))?;
+        Ok(filter_chapters(chapters, languages))
     }
 
+    // Urls of the pages, offline as well (from the cache). The saved files of a
+    // downloaded chapter are given by downloaded_pages
     pub fn chapter_page_list(&self, chapter: &EbiChapter) -> Result<Vec<String>, EbiError> {
+        let key = CacheKey::chapter_page_list(chapter);
+        let chapter = chapter.clone();
+        self.with_cache(&key, move |source| source.chapter_page_list(&chapter))
+    }
+
+    fn with_session<T, F>(&self, identifier: &str, call: F) -> Result<T, EbiError>
+    where
+        F: Fn(&Source) -> Result<T, EbiError>,
+    {
         let source = self
             .sources
-            .get(&chapter.source)
+            .get(identifier)
             .ok_or(EbiError::InvalidSource)?;
-        source.chapter_page_list(chapter)
+        with_session(&self.credentials, identifier, source, call)
+    }
+
+    // Shared, so async calls can run without holding on to the manager
+    #[cfg(feature = "async")]
+    pub(crate) fn source(&self, source: &str) -> Result<Arc<Source>, EbiError> {
+        self.sources
+            .get(source)
+            .cloned()
+            .ok_or(EbiError::InvalidSource)
     }
 }
 
 // Initialization/Setup
 impl SourceManager {
+    pub fn builder() -> SourceManagerBuilder {
+        SourceManagerBuilder::default()
+    }
+
+    // Uses dir_path as data, cache and config directory. Panics where builder().build()
+    // fails, e.g.: on an invalid config
     pub fn new(dir_path: &str) -> Self {
-        let dir_path = PathBuf::from(dir_path);
+        Self::builder()
+            .data_dir(dir_path)
+            .cache_dir(dir_path)
+            .config_dir(dir_path)
+            .build()
+            .unwrap_or_else(|e| panic!("Could not create SourceManager at {} :: {}", dir_path, e))
+    }
 
+    pub(crate) fn with_dirs(
+        data_dir: PathBuf,
+        cache_dir: PathBuf,
+        config_dir: PathBuf,
+        config: EbiConfig,
+        http: HttpClient,
+        credential_key: Option<CredentialKey>,
+    ) -> Self {
+        let cache = Arc::new(MetadataCache::new(&cache_dir));
+        let credentials = Arc::new(CredentialStore::new(&config_dir, credential_key));
+        let cookie_stores = Arc::new(CookieStores::new(data_dir.clone(), credentials.clone()));
         Self {
-            dir_path,
+            data_dir,
+            cache_dir,
+            config_dir,
+            config,
+            http,
             sources: HashMap::new(),
+            cookie_stores,
+            cache,
+            credentials,
+            search_pool: SearchPool::new(),
+            offline: false,
+            #[cfg(feature = "watcher")]
+            watcher: None,
         }
     }
 
+    pub fn data_dir(&self) -> PathBuf {
+        self.data_dir.clone()
+    }
+
+    #[deprecated(note = "use data_dir, cache_dir or config_dir")]
     pub fn dir(&self) -> PathBuf {
-        self.dir_path.clone()
+        self.data_dir.clone()
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir.clone()
+    }
+
+    pub fn config_dir(&self) -> PathBuf {
+        self.config_dir.clone()
+    }
+
+    pub fn config(&self) -> &EbiConfig {
+        &self.config
+    }
+
+    pub fn set_signature_policy(&mut self, policy: SignaturePolicy) -> Result<(), EbiError> {
+        self.config.signature_policy = policy;
+        self.config.save(&self.config_dir)
+    }
+
+    pub fn set_repository_signature_policy(
+        &mut self,
+        policy: SignaturePolicy,
+    ) -> Result<(), EbiError> {
+        self.config.repository_signature_policy = policy;
+        self.config.save(&self.config_dir)
+    }
+
+    // Languages the user reads, used to filter chapter lists. Every language when empty
+    pub fn languages(&self) -> &[Locale] {
+        &self.config.languages
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    // Offline, nothing is requested from sources or repositories: responses come from
+    // the cache, and login, logout, install_source and update_sources fail with
+    // EbiError::NotAvailableOffline. Sources are still loaded and initialized (e.g.: by
+    // set_preference), which must not need the network. Not stored, every manager
+    // starts online unless built with SourceManagerBuilder::offline
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    fn ensure_online(&self, operation: &str) -> Result<(), EbiError> {
+        match self.offline {
+            true => Err(EbiError::NotAvailableOffline(operation.to_owned())),
+            false => Ok(()),
+        }
+    }
+
+    pub fn set_languages(&mut self, languages: Vec<Locale>) -> Result<(), EbiError> {
+        self.config.languages = languages;
+        self.config.save(&self.config_dir)
+    }
+
+    // Applied to the requests made from now on
+    pub fn set_http_config(&mut self, http: HttpConfig) -> Result<(), EbiError> {
+        self.http = HttpClient::new(&http)?;
+        self.config.http = http;
+        self.config.save(&self.config_dir)
     }
 
-    // <self.dir>/sources
+    pub(crate) fn http(&self) -> HttpClient {
+        self.http.clone()
+    }
+
+    // Base64 encoded minisign public key, as surfaced by EbiError::UntrustedSourceKey
+    pub fn trust_key(&mut self, public_key: &str) -> Result<(), EbiError> {
+        validate_public_key(public_key)?;
+        if !self.config.trusted_keys.iter().any(|k| k == public_key) {
+            self.config.trusted_keys.push(public_key.to_owned());
+        }
+        self.config.save(&self.config_dir)
+    }
+
+    pub fn untrust_key(&mut self, public_key: &str) -> Result<(), EbiError> {
+        self.config.trusted_keys.retain(|k| k != public_key);
+        self.config.save(&self.config_dir)
+    }
+
+    // <self.data_dir>/sources
     pub fn source_dir(&self) -> PathBuf {
-        let mut source_dir = self.dir_path.clone();
+        let mut source_dir = self.data_dir.clone();
         source_dir.push("sources");
         source_dir
     }
 
-    // TODO: Refactor this later (probably after adding "install source" support)
-    pub fn load_sources(&mut self) -> Result<(), EbiError> {
-        let source_dir_entries = std::fs::read_dir(self.source_dir()).unwrap();
+    // Reads every installed source manifest, without loading any library. Only fails if
+    // the source directory can't be read, each invalid manifest is in the report
+    pub fn manifests(&self) -> Result<ManifestReport, EbiError> {
+        let source_dir = self.source_dir();
+        let mut report = ManifestReport::default();
+        if !source_dir.exists() {
+            return Ok(report);
+        }
+        let source_dir_entries = std::fs::read_dir(source_dir)?;
+
+        for dir in source_dir_entries
+            .flatten()
+            .filter(|dir| dir.file_name() != STAGING_DIR_NAME)
+            .map(|dir| dir.path())
+        {
+            match read_manifest(&dir) {
+                Ok(Some(manifest)) => report.manifests.push(manifest),
+                Ok(None) => {}
+                Err(e) => {
+                    log::warn!("Could not read manifest at {} :: {}", dir.display(), e);
+                    report.errors.push((dir, e));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    pub fn manifest(&self, identifier: &str) -> Result<SourceManifest, EbiError> {
+        read_manifest(&self.installed_source_dir(identifier)?)?
+            .ok_or_else(|| EbiError::SourceNotInstalled(identifier.to_owned()))
+    }
+
+    // Only fails if the source directory can't be read, each source outcome is in the report
+    pub fn load_sources(&mut self) -> Result<LoadReport, EbiError> {
+        let source_dir = self.source_dir();
+        std::fs::create_dir_all(&source_dir)?;
+        let source_dir_entries = std::fs::read_dir(source_dir)?;
 
         let source_dir_directories = source_dir_entries
-            .filter(|d| d.is_ok())
-            .map(|d| d.unwrap())
+            .flatten()
             .filter(|d| d.metadata().is_ok())
-            .filter(|d| d.metadata().unwrap().is_dir());
+            .filter(|d| d.metadata().unwrap().is_dir())
+            .filter(|d| d.file_name() != STAGING_DIR_NAME);
 
+        let mut report = LoadReport::default();
         for dir in source_dir_directories {
-            let identifier = dir.file_name();
-            let identifier = identifier.to_str();
+            let dir = dir.path();
 
-            if let None = identifier {
-                log::warn!(
-                    "Could not load source file at {} :: invalid os file_name",
-                    dir.path().display()
-                );
+            let identifier = match source_identifier(&dir) {
+                Ok(identifier) => identifier.to_owned(),
+                Err(e) => {
+                    log::warn!("Could not load source at {} :: {}", dir.display(), e);
+                    report.push(dir, LoadOutcome::from(e));
+                    continue;
+                }
+            };
+            if self.sources.contains_key(&identifier) {
+                report.push(dir, LoadOutcome::AlreadyLoaded(identifier));
                 continue;
             }
 
-            let target_file = handle_source_file_extension(identifier.unwrap());
-
-            let mut file_path = dir.path();
-            file_path.push(target_file);
-
+            let file_path = source_lib_path(&dir, &identifier);
             if !file_path.exists() {
-                log::warn!(
-                    "Could not load source file {} :: file does not exists",
-                    file_path.display()
-                );
+                log::warn!("No source library at {}", file_path.display());
+                report.push(dir, LoadOutcome::MissingLibrary(file_path));
                 continue;
             }
 
-            match Source::try_from(file_path) {
-                Ok(source) => {
-                    let identifier = source.source_info()?.identifier.clone();
-                    log::info!("Loaded source {}", &identifier);
-                    self.sources.insert(identifier, source);
-                }
+            let outcome = match self.load_source(&dir) {
+                Ok(identifier) => LoadOutcome::Loaded(identifier),
                 Err(e) => {
-                    println!("ERROR::{}", e);
+                    log::error!("Could not load source at {} :: {}", dir.display(), e);
+                    LoadOutcome::from(e)
                 }
+            };
+            report.push(dir, outcome);
+        }
+
+        Ok(report)
+    }
+
+    fn load_source(&mut self, dir: &Path) -> Result<String, EbiError> {
+        let (identifier, source) = self.open_source(dir)?;
+        if self.sources.contains_key(&identifier) {
+            return Err(EbiError::DuplicatedSource(identifier));
+        }
+
+        log::info!("Loaded source {}", &identifier);
+        self.sources.insert(identifier.clone(), Arc::new(source));
+        Ok(identifier)
+    }
+
+    // Loads the library, logged in, without adding it to the loaded sources
+    fn open_source(&self, dir: &Path) -> Result<(String, Source), EbiError> {
+        let identifier = source_identifier(dir)?;
+        let file_path = source_lib_path(dir, identifier);
+        if !file_path.exists() {
+            log::warn!(
+                "Could not load source file {} :: file does not exists",
+                file_path.display()
+            );
+            return Err(EbiError::SourceNotInstalled(identifier.to_owned()));
+        }
+
+        match read_manifest(dir)? {
+            Some(manifest) => validate_manifest(identifier, &manifest)?,
+            None => log::warn!("Source {} has no manifest", identifier),
+        }
+
+        let policy = match InstalledSource::read(dir) {
+            Some(_) => self.config.repository_signature_policy,
+            None => self.config.signature_policy,
+        };
+        let lib = verify_source(identifier, &file_path, policy, &self.config.trusted_keys)?;
+        let lib_path = library_copy(&self.cache_dir, identifier, &lib)?;
+
+        let preferences = read_preferences(&self.config_dir, identifier)?;
+        let mut source = Source::load(identifier, lib_path, &preferences)?;
+        // Everything stored for the source (preferences, credentials, downloads) is
+        // found through its directory
+        let loaded = source.source_info()?.identifier;
+        if loaded != identifier {
+            return Err(EbiError::IdentifierMismatch(identifier.to_owned(), loaded));
+        }
+        let identifier = loaded;
+
+        source.set_cookie_store(self.cookie_stores.get(&identifier)?);
+
+        // Sessions are not kept across loads. A failed login is not fatal, calls will report
+        // LoginRequired
+        if source.functions().login {
+            let login = match self.credentials.read(&identifier) {
+                Ok(Some(credentials)) => source.login(&credentials),
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = login {
+                log::warn!("Could not log in to {} :: {}", identifier, e);
             }
         }
 
+        Ok((identifier, source))
+    }
+}
+
+// Preferences
+impl SourceManager {
+    pub fn preferences_schema(&self, identifier: &str) -> Result<PreferenceSchema, EbiError> {
+        let source = self
+            .sources
+            .get(identifier)
+            .ok_or(EbiError::InvalidSource)?;
+        Ok(source.preferences_schema().clone())
+    }
+
+    // Stored values, with defaults for the ones never set
+    pub fn preferences(&self, identifier: &str) -> Result<Preferences, EbiError> {
+        let schema = self.preferences_schema(identifier)?;
+        let stored = read_preferences(&self.config_dir, identifier)?;
+        Ok(schema.resolve(&stored))
+    }
+
+    // Sources get their preferences at init, so the source is reloaded to apply the value
+    pub fn set_preference(
+        &mut self,
+        identifier: &str,
+        key: &str,
+        value: PreferenceValue,
+    ) -> Result<(), EbiError> {
+        let schema = self.preferences_schema(identifier)?;
+        let preference = schema
+            .preference(key)
+            .ok_or_else(|| EbiError::InvalidPreference(key.to_owned()))?;
+        if !preference.accepts(&value) {
+            return Err(EbiError::InvalidPreference(key.to_owned()));
+        }
+
+        let previous = read_preferences(&self.config_dir, identifier)?;
+        let mut stored = previous.clone();
+        stored.set(key, value);
+        write_preferences(&self.config_dir, identifier, &stored)?;
+
+        self.reload_preferences(identifier, previous)
+    }
+
+    pub fn reset_preferences(&mut self, identifier: &str) -> Result<(), EbiError> {
+        if !self.sources.contains_key(identifier) {
+            return Err(EbiError::InvalidSource);
+        }
+
+        let previous = read_preferences(&self.config_dir, identifier)?;
+        remove_preferences(&self.config_dir, identifier)?;
+        self.reload_preferences(identifier, previous)
+    }
+
+    // A source that fails to reload keeps running with its previous values, which are
+    // then stored back so the next load matches it
+    fn reload_preferences(
+        &mut self,
+        identifier: &str,
+        previous: Preferences,
+    ) -> Result<(), EbiError> {
+        let reloaded = self.reload_source(identifier);
+        if reloaded.is_err() {
+            write_preferences(&self.config_dir, identifier, &previous)?;
+        }
+        reloaded
+    }
+}
+
+// Authentication
+impl SourceManager {
+    // Credentials are only stored, encrypted, once the source accepted them
+    pub fn login(&self, identifier: &str, credentials: Credentials) -> Result<(), EbiError> {
+        self.ensure_online(&format!("login::{}", identifier))?;
+        let source = self
+            .sources
+            .get(identifier)
+            .ok_or(EbiError::InvalidSource)?;
+        source.login(&credentials)?;
+        self.credentials.write(identifier, &credentials)
+    }
+
+    pub fn logout(&self, identifier: &str) -> Result<(), EbiError> {
+        self.ensure_online(&format!("logout::{}", identifier))?;
+        let source = self
+            .sources
+            .get(identifier)
+            .ok_or(EbiError::InvalidSource)?;
+        self.credentials.remove(identifier)?;
+        source.logout()
+    }
+
+    pub fn has_credentials(&self, identifier: &str) -> bool {
+        matches!(self.credentials.read(identifier), Ok(Some(_)))
+    }
+
+    // Replaces the key set with SourceManagerBuilder::credential_key, credentials stored
+    // with another key can no longer be read
+    pub fn set_credential_key(&self, key: Option<CredentialKey>) {
+        self.credentials.set_key(key);
+    }
+}
+
+// Cookies
+impl SourceManager {
+    // Stored cookies of a source, loaded or not
+    pub fn cookies(&self, identifier: &str) -> Result<Vec<Cookie>, EbiError> {
+        Ok(self.cookie_store(identifier)?.cookies())
+    }
+
+    // Loaded sources keep their in-memory session, so they may need to log back in
+    pub fn clear_cookies(&self, identifier: &str) -> Result<(), EbiError> {
+        self.cookie_store(identifier)?.clear()
+    }
+
+    // Writes the pending cookie changes now, instead of within a couple of seconds.
+    // Fails when they can't be saved, e.g.: without a credential key
+    pub fn save_cookies(&self, identifier: &str) -> Result<(), EbiError> {
+        self.cookie_store(identifier)?.flush()
+    }
+
+    // Why the cookies of the source could not be saved in the background, None once
+    // they are. They are still used by the running session
+    pub fn cookies_save_error(&self, identifier: &str) -> Result<Option<String>, EbiError> {
+        Ok(self.cookie_store(identifier)?.save_error())
+    }
+
+    fn cookie_store(&self, identifier: &str) -> Result<Arc<CookieStore>, EbiError> {
+        self.cookie_stores.get(identifier)
+    }
+
+    pub(crate) fn cookie_stores(&self) -> Arc<CookieStores> {
+        self.cookie_stores.clone()
+    }
+}
+
+// Migration
+impl SourceManager {
+    // Searches the target source for the manga title, most similar titles first
+    pub fn migration_candidates(
+        &self,
+        manga: &EbiManga,
+        target: &str,
+    ) -> Result<Vec<MigrationCandidate>, EbiError> {
+        let found = self.search(target, &manga.title)?;
+        Ok(MigrationCandidate::ranked(manga, found))
+    }
+
+    // Chapters of `from` in every language, matched with the ones of `to` in the
+    // languages set with `set_languages`
+    pub fn migrate(&self, from: &EbiManga, to: &EbiManga) -> Result<Migration, EbiError> {
+        let chapters = self.chapter_list_in(from, &[])?;
+        self.migrate_chapters(from, chapters, to)
+    }
+
+    // When the source of `from` can't be reached anymore, e.g.: with the chapters
+    // stored by the frontend
+    pub fn migrate_chapters(
+        &self,
+        from: &EbiManga,
+        chapters: Vec<EbiChapter>,
+        to: &EbiManga,
+    ) -> Result<Migration, EbiError> {
+        let target_chapters = self.chapter_list(to)?;
+        Ok(Migration::new(
+            from.clone(),
+            chapters,
+            to.clone(),
+            &target_chapters,
+        ))
+    }
+}
+
+// Downloads
+impl SourceManager {
+    // Every page of the chapter was saved with SourceArchiver::save_chapter
+    pub fn is_downloaded(&self, chapter: &EbiChapter) -> bool {
+        SourceArchiver::from(self).is_downloaded(chapter)
+    }
+
+    pub fn downloaded_chapters(&self, manga: &EbiManga) -> Result<Vec<EbiChapter>, EbiError> {
+        SourceArchiver::from(self).downloaded_chapters(manga)
+    }
+
+    // Saved files of a downloaded chapter, in page order, e.g.: to read it offline
+    pub fn downloaded_pages(&self, chapter: &EbiChapter) -> Result<Vec<String>, EbiError> {
+        SourceArchiver::from(self).downloaded_pages(chapter)
+    }
+
+    // chapter_list, with the chapters that can be read offline
+    pub fn chapter_list_with_downloads(
+        &self,
+        manga: &EbiManga,
+    ) -> Result<Vec<ListedChapter>, EbiError> {
+        Ok(self.mark_downloaded(self.chapter_list(manga)?))
+    }
+
+    pub fn mark_downloaded(&self, chapters: Vec<EbiChapter>) -> Vec<ListedChapter> {
+        let archiver = SourceArchiver::from(self);
+        chapters
+            .into_iter()
+            .map(|chapter| ListedChapter {
+                downloaded: archiver.is_downloaded(&chapter),
+                chapter,
+            })
+            .collect()
+    }
+
+    // See SourceArchiver::migrate_downloads. The cached chapter and page lists of the
+    // migrated manga are dropped as well, so they aren't served (e.g.: offline) for
+    // chapters that are no longer downloaded
+    pub fn migrate_downloads(&self, migration: &Migration) -> Result<Vec<EbiChapter>, EbiError> {
+        let migrated = SourceArchiver::from(self).migrate_downloads(migration)?;
+        if !migrated.is_empty() {
+            self.invalidate_chapters(&migration.from)?;
+        }
+        Ok(migrated)
+    }
+
+    // The cached chapter list, in these or in every language, or else the downloaded
+    // chapters
+    fn offline_chapter_list(
+        &self,
+        manga: &EbiManga,
+        languages: &[Locale],
+    ) -> Result<Vec<EbiChapter>, EbiError> {
+        let cached = [
+            CacheKey::chapter_list(manga, languages),
+            CacheKey::chapter_list(manga, &[]),
+        ]
+        .iter()
+        .find_map(|key| self.cache.get::<Vec<EbiChapter>>(key));
+        if let Some((chapters, _)) = cached {
+            return Ok(filter_chapters(chapters, languages));
+        }
+
+        let chapters = self.downloaded_chapters(manga)?;
+        if chapters.is_empty() {
+            return Err(EbiError::NotAvailableOffline(format!(
+                "chapter_list::{}::{}",
+                manga.source, manga.identifier
+            )));
+        }
+        Ok(filter_chapters(chapters, languages))
+    }
+}
+
+// Cache
+impl SourceManager {
+    pub fn set_cache_config(&mut self, cache: CacheConfig) -> Result<(), EbiError> {
+        self.config.cache = cache;
+        self.config.save(&self.config_dir)
+    }
+
+    // Every cached response of a source, e.g.: once it was updated
+    pub fn invalidate_cache(&self, source: &str) -> Result<(), EbiError> {
+        self.cache.invalidate(source, None)
+    }
+
+    // e.g.: to look for new manga
+    pub fn invalidate_manga_list(&self, source: &str) -> Result<(), EbiError> {
+        self.cache
+            .invalidate(source, Some(CacheOperation::MangaList))
+    }
+
+    // Chapter and page lists of a manga, e.g.: to look for new chapters
+    pub fn invalidate_chapters(&self, manga: &EbiManga) -> Result<(), EbiError> {
+        for operation in [CacheOperation::ChapterList, CacheOperation::ChapterPageList] {
+            self.cache
+                .invalidate_manga(&manga.source, operation, &manga.identifier)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear_cache(&self) -> Result<(), EbiError> {
+        self.cache.clear()
+    }
+
+    // Fresh responses, or stale ones still within config.cache.stale_while_revalidate,
+    // which are then refreshed in the background. With their age
+    pub(crate) fn cached_with_age<T, F>(&self, key: &CacheKey, call: F) -> Option<(T, Duration)>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: Fn(&Source) -> Result<T, EbiError> + Send + 'static,
+    {
+        let ttl = self.config.cache.ttl(key.operation);
+        if ttl.is_zero() {
+            return None;
+        }
+
+        let (value, age) = self.cache.get(key)?;
+        if age <= ttl {
+            return Some((value, age));
+        }
+        if age > ttl + Duration::from_secs(self.config.cache.stale_while_revalidate) {
+            return None;
+        }
+
+        self.revalidate(key, call);
+        Some((value, age))
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn cache_response<T>(
+        &self,
+        key: &CacheKey,
+        result: Result<T, EbiError>,
+    ) -> Result<T, EbiError>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        self.cache_response_with_age(key, result)
+            .map(|(value, _)| value)
+    }
+
+    // Stores successful responses. Calls that failed on the network (see
+    // EbiError::is_retryable) get the cached response instead, however old, with its
+    // age. Other errors, e.g.: a removed manga, are returned as they are
+    pub(crate) fn cache_response_with_age<T>(
+        &self,
+        key: &CacheKey,
+        result: Result<T, EbiError>,
+    ) -> Result<(T, Option<Duration>), EbiError>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let enabled = !self.config.cache.ttl(key.operation).is_zero();
+        cache_response(&self.cache, enabled, key, result)
+    }
+
+    fn with_cache<T, F>(&self, key: &CacheKey, call: F) -> Result<T, EbiError>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: Fn(&Source) -> Result<T, EbiError> + Clone + Send + 'static,
+    {
+        self.with_cache_age(key, call).map(|(value, _)| value)
+    }
+
+    // Age of the response when it comes from the cache
+    fn with_cache_age<T, F>(
+        &self,
+        key: &CacheKey,
+        call: F,
+    ) -> Result<(T, Option<Duration>), EbiError>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: Fn(&Source) -> Result<T, EbiError> + Clone + Send + 'static,
+    {
+        if self.offline {
+            return self
+                .cache
+                .get(key)
+                .map(|(value, age)| (value, Some(age)))
+                .ok_or_else(|| {
+                    EbiError::NotAvailableOffline(format!(
+                        "{}::{}",
+                        key.operation.as_str(),
+                        key.source
+                    ))
+                });
+        }
+
+        if let Some((value, age)) = self.cached_with_age(key, call.clone()) {
+            return Ok((value, Some(age)));
+        }
+
+        let result = self.with_session(&key.source, call);
+        self.cache_response_with_age(key, result)
+    }
+
+    // At most one refresh per response, sources that are not loaded are not refreshed
+    fn revalidate<T, F>(&self, key: &CacheKey, call: F)
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: Fn(&Source) -> Result<T, EbiError> + Send + 'static,
+    {
+        let source = match self.sources.get(&key.source) {
+            Some(source) => source.clone(),
+            None => return,
+        };
+        if !self.cache.start_revalidation(key) {
+            return;
+        }
+
+        let cache = self.cache.clone();
+        let credentials = self.credentials.clone();
+        let key = key.clone();
+        std::thread::spawn(move || {
+            match with_session(&credentials, &key.source, &source, call) {
+                Ok(value) => cache.insert(&key, &value),
+                Err(e) => log::warn!(
+                    "Could not refresh {} of {} :: {}",
+                    key.operation.as_str(),
+                    key.source,
+                    e
+                ),
+            }
+            cache.end_revalidation(&key);
+        });
+    }
+}
+
+// Reloading
+impl SourceManager {
+    // Taking &mut self ensures no call into the library is in progress. Data returned
+    // by sources is owned by the host, so nothing borrowed outlives the library.
+    // In-flight async calls keep the library loaded until they are done
+    pub fn unload_source(&mut self, identifier: &str) -> Result<(), EbiError> {
+        self.sources
+            .remove(identifier)
+            .ok_or(EbiError::InvalidSource)?;
+        log::info!("Unloaded source {}", identifier);
+        Ok(())
+    }
+
+    // The new library is loaded before the current one is dropped, which keeps serving
+    // calls if loading fails. Each library content is loaded from its own copy, so the
+    // loader never hands back the one still mapped
+    pub fn reload_source(&mut self, identifier: &str) -> Result<(), EbiError> {
+        let dir = self.installed_source_dir(identifier)?;
+        self.replace_source(&dir)?;
+        Ok(())
+    }
+
+    // Loads the source at `dir` in place of the loaded one with the same identifier, if any
+    fn replace_source(&mut self, dir: &Path) -> Result<String, EbiError> {
+        let (identifier, source) = self.open_source(dir)?;
+        let replaced = self.sources.insert(identifier.clone(), Arc::new(source));
+        match replaced {
+            Some(_) => log::info!("Reloaded source {}", identifier),
+            None => log::info!("Loaded source {}", identifier),
+        }
+        Ok(identifier)
+    }
+
+    #[cfg(feature = "watcher")]
+    pub fn watch_sources(&mut self) -> Result<(), EbiError> {
+        self.watcher = Some(SourceWatcher::new(self.source_dir())?);
         Ok(())
     }
+
+    #[cfg(feature = "watcher")]
+    pub fn unwatch_sources(&mut self) {
+        self.watcher = None;
+    }
+
+    // Reloads every source changed on disk since the last call, once its library stopped
+    // changing. Meant to be polled by the SourceManager owner, e.g.: once per UI frame
+    #[cfg(feature = "watcher")]
+    pub fn reload_changed_sources(&mut self) -> Vec<(String, Result<(), EbiError>)> {
+        let changed = match self.watcher {
+            Some(ref watcher) => watcher.changed_sources(),
+            None => return Vec::new(),
+        };
+
+        changed
+            .into_iter()
+            .map(|identifier| {
+                let result = self.reload_source(&identifier);
+                if let Err(ref e) = result {
+                    log::error!("Could not reload source {} :: {}", identifier, e);
+                }
+                (identifier, result)
+            })
+            .collect()
+    }
+}
+
+// Installation
+impl SourceManager {
+    pub fn install_source(&mut self, index_url: &str, identifier: &str) -> Result<(), EbiError> {
+        self.ensure_online(&format!("install_source::{}", identifier))?;
+        let index = RepositoryIndex::fetch(&self.http, index_url)?;
+        let source = index.source(identifier)?;
+        self.install_from_repository(source)
+    }
+
+    pub fn uninstall_source(&mut self, identifier: &str) -> Result<(), EbiError> {
+        let dir = self.installed_source_dir(identifier)?;
+        let file_path = source_lib_path(&dir, identifier);
+
+        if !file_path.exists() {
+            return Err(EbiError::SourceNotInstalled(identifier.to_owned()));
+        }
+
+        // Unload before removing the file, so the library is not mapped anymore.
+        // The source directory itself is kept, as it also holds archived manga
+        self.sources.remove(identifier);
+        std::fs::remove_file(&file_path)?;
+        let signature_path = signature_path(&file_path);
+        if signature_path.exists() {
+            std::fs::remove_file(signature_path)?;
+        }
+        InstalledSource::remove(&dir)?;
+
+        let mut manifest_path = dir;
+        manifest_path.push(MANIFEST_FILE_NAME);
+        if manifest_path.exists() {
+            std::fs::remove_file(manifest_path)?;
+        }
+
+        log::info!("Uninstalled source {}", identifier);
+        Ok(())
+    }
+
+    // Returns the identifiers of every updated source
+    pub fn update_sources(&mut self, index_url: &str) -> Result<Vec<String>, EbiError> {
+        self.ensure_online("update_sources")?;
+        let index = RepositoryIndex::fetch(&self.http, index_url)?;
+
+        let mut updated = Vec::new();
+        for source in index.sources.iter() {
+            let installed = InstalledSource::read(self.installed_source_dir(&source.identifier)?);
+            match installed {
+                Some(installed) if installed.version != source.version => {
+                    self.install_from_repository(source)?;
+                    updated.push(source.identifier.clone());
+                }
+                _ => continue,
+            }
+        }
+
+        Ok(updated)
+    }
+
+    // Staged and loaded apart from the installed source, which is only replaced once the
+    // new one was verified and loaded: a refused update leaves it as it was
+    fn install_from_repository(&mut self, source: &RepositorySource) -> Result<(), EbiError> {
+        let artifact = source.host_artifact()?;
+        // Refused before downloading, as it would not be loaded
+        if artifact.signature.is_none()
+            && self.config.repository_signature_policy == SignaturePolicy::RequireSigned
+        {
+            return Err(EbiError::UnsignedSource(source.identifier.clone()));
+        }
+        let dir = self.installed_source_dir(&source.identifier)?;
+        let library = artifact.download(&self.http)?;
+        let signature = artifact.download_signature(&self.http)?;
+        let manifest = match source.manifest {
+            Some(ref manifest) => Some(fetch(&self.http, manifest)?),
+            None => None,
+        };
+
+        let mut staging_dir = self.source_dir();
+        staging_dir.push(STAGING_DIR_NAME);
+        staging_dir.push(&source.identifier);
+        if staging_dir.exists() {
+            std::fs::remove_dir_all(&staging_dir)?;
+        }
+        std::fs::create_dir_all(&staging_dir)?;
+
+        let staged = stage_source(&staging_dir, source, artifact, library, signature, manifest)
+            .and_then(|_| self.open_source(&staging_dir))
+            .and_then(|(identifier, loaded)| {
+                std::fs::create_dir_all(&dir)?;
+                let file_path = source_lib_path(&dir, &identifier);
+                // An unsigned update does not keep the signature of the previous one
+                let signature_path = signature_path(&file_path);
+                if artifact.signature.is_none() && signature_path.exists() {
+                    std::fs::remove_file(signature_path)?;
+                }
+                // The loaded library is a copy, so it is never overwritten in place
+                for file in std::fs::read_dir(&staging_dir)? {
+                    let file = file?;
+                    std::fs::rename(file.path(), dir.join(file.file_name()))?;
+                }
+                Ok((identifier, loaded))
+            });
+        if let Err(e) = std::fs::remove_dir_all(&staging_dir) {
+            log::warn!(
+                "Could not remove staged source {} :: {}",
+                staging_dir.display(),
+                e
+            );
+        }
+        let (identifier, loaded) = staged?;
+
+        let replaced = self.sources.insert(identifier, Arc::new(loaded));
+        if replaced.is_some() {
+            log::info!("Reloaded source {}", source.identifier);
+        }
+        log::info!(
+            "Installed source {} ({})",
+            source.identifier,
+            source.version
+        );
+        Ok(())
+    }
+
+    // <self.source_dir>/<identifier>
+    fn installed_source_dir(&self, identifier: &str) -> Result<PathBuf, EbiError> {
+        validate_identifier(identifier)?;
+        let mut dir = self.source_dir();
+        dir.push(identifier);
+        Ok(dir)
+    }
+}
+
+// Writes every file of the installed source to `dir`
+fn stage_source(
+    dir: &Path,
+    source: &RepositorySource,
+    artifact: &RepositoryArtifact,
+    library: Vec<u8>,
+    signature: Option<Vec<u8>>,
+    manifest: Option<Vec<u8>>,
+) -> Result<(), EbiError> {
+    let write = |path: PathBuf, contents: &[u8]| {
+        std::fs::write(path, contents).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))
+    };
+
+    let file_path = source_lib_path(dir, &source.identifier);
+    write(file_path.clone(), &library)?;
+    if let Some(signature) = signature {
+        write(signature_path(&file_path), &signature)?;
+    }
+    if let Some(manifest) = manifest {
+        write(dir.join(MANIFEST_FILE_NAME), &manifest)?;
+    }
+    if let Some(ref public_key) = source.public_key {
+        let public_key = format!(
+            "untrusted comment: {} public key\n{}\n",
+            source.identifier, public_key
+        );
+        write(dir.join(PUBLIC_KEY_FILE_NAME), public_key.as_bytes())?;
+    }
+
+    InstalledSource {
+        identifier: source.identifier.clone(),
+        version: source.version.clone(),
+        sha256: artifact.sha256.clone(),
+    }
+    .write(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(dir: &Path) -> SourceManager {
+        SourceManager::builder()
+            .data_dir(dir)
+            .cache_dir(dir)
+            .config_dir(dir)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn cached_responses_only_replace_network_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = manager(dir.path());
+        let key = CacheKey::search("source", "query");
+        manager.cache.insert(&key, &vec![1]);
+
+        let result = manager
+            .cache_response_with_age::<Vec<i32>>(&key, Err(EbiError::Timeout("search".to_owned())));
+        let (value, age) = result.unwrap();
+        assert_eq!(value, [1]);
+        assert!(age.is_some());
+
+        let not_found =
+            EbiError::from_source("source", "search", ebi_source::error::SourceError::NotFound);
+        let result = manager.cache_response_with_age::<Vec<i32>>(&key, Err(not_found));
+        assert!(matches!(result, Err(EbiError::Source { .. })));
+
+        let (value, age) = manager.cache_response_with_age(&key, Ok(vec![2])).unwrap();
+        assert_eq!((value, age), (vec![2], None));
+    }
+
+    #[test]
+    fn global_search_serves_cached_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = manager(dir.path());
+        manager.cache.insert(
+            &CacheKey::search("cached", "query"),
+            &Vec::<EbiManga>::new(),
+        );
+
+        let mut results = manager
+            .global_search_in("query", &["cached".to_owned(), "missing".to_owned()])
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| a.source.cmp(&b.source));
+
+        assert_eq!(results[0].source, "cached");
+        assert!(results[0].result.is_ok() && results[0].cached.is_some());
+        assert_eq!(results[1].source, "missing");
+        assert!(matches!(results[1].result, Err(EbiError::InvalidSource)));
+        assert!(results[1].cached.is_none());
+    }
+
+    fn chapter(chapter: f32) -> EbiChapter {
+        EbiChapter {
+            chapter,
+            title: String::new(),
+            url: format!("https://example.com/{}", chapter),
+            manga: "manga".to_owned(),
+            source: "source".to_owned(),
+            language: Locale::EN,
+        }
+    }
+
+    #[test]
+    fn offline_is_not_stored_and_guards_network_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = manager(dir.path());
+        manager.set_offline(true);
+
+        let credentials = Credentials::Token {
+            token: "token".to_owned(),
+        };
+        for result in [
+            manager.login("source", credentials),
+            manager.logout("source"),
+            manager.install_source("https://example.com/index.json", "source"),
+            manager
+                .update_sources("https://example.com/index.json")
+                .map(|_| ()),
+        ] {
+            assert!(matches!(result, Err(EbiError::NotAvailableOffline(_))));
+        }
+
+        assert!(!self::manager(dir.path()).is_offline());
+        let built = SourceManager::builder()
+            .data_dir(dir.path())
+            .cache_dir(dir.path())
+            .config_dir(dir.path())
+            .offline(true)
+            .build()
+            .unwrap();
+        assert!(built.is_offline());
+    }
+
+    #[test]
+    fn offline_page_lists_are_urls_and_downloads_are_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = manager(dir.path());
+        manager.set_offline(true);
+        let (saved, listed) = (chapter(1.0), chapter(2.0));
+
+        let pages = vec!["https://example.com/1/0.png".to_owned()];
+        manager
+            .cache
+            .insert(&CacheKey::chapter_page_list(&saved), &pages);
+        assert_eq!(manager.chapter_page_list(&saved).unwrap(), pages);
+        assert!(matches!(
+            manager.chapter_page_list(&listed),
+            Err(EbiError::NotAvailableOffline(_))
+        ));
+
+        let chapter_path = manager.source_dir().join("source/manga/manga/1_en");
+        std::fs::create_dir_all(&chapter_path).unwrap();
+        std::fs::write(chapter_path.join("0.png"), b"page").unwrap();
+        SourceArchiver::from(&manager)
+            .save_chapter(&saved, &pages)
+            .unwrap();
+
+        let files = manager.downloaded_pages(&saved).unwrap();
+        assert_eq!(files, [chapter_path.join("0.png").to_string_lossy()]);
+
+        let marked = manager.mark_downloaded(vec![saved, listed]);
+        let downloaded: Vec<bool> = marked.iter().map(|c| c.downloaded).collect();
+        assert_eq!(downloaded, [true, false]);
+    }
+
+    #[test]
+    fn source_directories_without_a_library_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = manager(dir.path());
+        std::fs::create_dir_all(manager.source_dir().join("example")).unwrap();
+
+        let report = manager.load_sources().unwrap();
+        assert!(matches!(
+            report.entries[0].outcome,
+            LoadOutcome::MissingLibrary(_)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_source_directories_are_rejected() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = manager(dir.path());
+        let name = std::ffi::OsStr::from_bytes(b"example\xff");
+        std::fs::create_dir_all(manager.source_dir().join(name)).unwrap();
+
+        let report = manager.load_sources().unwrap();
+        assert!(matches!(
+            report.entries[0].outcome,
+            LoadOutcome::Rejected(EbiError::InvalidDir(_))
+        ));
+    }
+
+    #[test]
+    fn refused_installs_keep_the_installed_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = manager(dir.path());
+        let source_dir = manager.installed_source_dir("example").unwrap();
+        std::fs::create_dir_all(&source_dir).unwrap();
+        let file_path = source_lib_path(&source_dir, "example");
+        std::fs::write(&file_path, b"installed").unwrap();
+
+        let repository = dir.path().join("repository");
+        std::fs::create_dir_all(&repository).unwrap();
+        std::fs::write(repository.join("library"), b"update").unwrap();
+        std::fs::write(repository.join("library.minisig"), b"not a signature").unwrap();
+        let index = serde_json::json!({
+            "sources": [{
+                "identifier": "example",
+                "version": "2.0.0",
+                "abi_version": ebi_source::abi::ABI_VERSION,
+                "artifacts": {
+                    super::super::repository::host_target(): {
+                        "url": "library",
+                        "sha256": hex::encode(<sha2::Sha256 as sha2::Digest>::digest(b"update")),
+                        "signature": "library.minisig"
+                    }
+                }
+            }]
+        });
+        std::fs::write(repository.join("index.json"), index.to_string()).unwrap();
+
+        let index_url = format!("file://{}", repository.join("index.json").display());
+        let result = manager.install_source(&index_url, "example");
+        assert!(matches!(result, Err(EbiError::InvalidSignature(_))));
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"installed");
+        assert!(!signature_path(&file_path).exists());
+        assert!(InstalledSource::read(&source_dir).is_none());
+        assert!(!manager
+            .source_dir()
+            .join(STAGING_DIR_NAME)
+            .join("example")
+            .exists());
+
+        assert!(matches!(
+            manager.install_source(&index_url, "../example"),
+            Err(EbiError::SourceNotInRepository(_))
+        ));
+        assert!(matches!(
+            manager.uninstall_source("../example"),
+            Err(EbiError::InvalidIdentifier(_))
+        ));
+    }
 }
@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use ebi_source::manifest::{SourceManifest, MANIFEST_FILE_NAME};
+
+use crate::error::EbiError;
+
+// A missing manifest is not an error, as older sources do not ship one
+pub(crate) fn read_manifest(source_dir: &Path) -> Result<Option<SourceManifest>, EbiError> {
+    let mut path = source_dir.to_path_buf();
+    path.push(MANIFEST_FILE_NAME);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let manifest = std::fs::read(&path)?;
+    let manifest = serde_json::from_slice(&manifest)
+        .map_err(|e| EbiError::InvalidManifest(format!("{} :: {}", path.display(), e)))?;
+    Ok(Some(manifest))
+}
+
+pub(crate) fn validate_manifest(
+    identifier: &str,
+    manifest: &SourceManifest,
+) -> Result<(), EbiError> {
+    if manifest.identifier != identifier {
+        return Err(EbiError::InvalidManifest(format!(
+            "{} :: identifier {} does not match its directory",
+            identifier, manifest.identifier
+        )));
+    }
+
+    if !ebi_source::abi::is_compatible(manifest.abi_version) {
+        return Err(EbiError::IncompatibleAbiVersion(manifest.abi_version));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(identifier: &str, abi_version: u32) -> SourceManifest {
+        serde_json::from_value(serde_json::json!({
+            "identifier": identifier,
+            "title": "Example",
+            "version": "0.1.0",
+            "abi_version": abi_version,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn manifests_are_checked_against_their_directory_and_abi() {
+        let abi_version = ebi_source::abi::ABI_VERSION;
+        assert!(validate_manifest("example", &manifest("example", abi_version)).is_ok());
+        assert!(matches!(
+            validate_manifest("other", &manifest("example", abi_version)),
+            Err(EbiError::InvalidManifest(_))
+        ));
+        assert!(matches!(
+            validate_manifest("example", &manifest("example", abi_version - 1)),
+            Err(EbiError::IncompatibleAbiVersion(_))
+        ));
+    }
+
+    #[test]
+    fn missing_manifests_are_not_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_manifest(dir.path()).unwrap().is_none());
+
+        std::fs::write(dir.path().join(MANIFEST_FILE_NAME), "{").unwrap();
+        assert!(matches!(
+            read_manifest(dir.path()),
+            Err(EbiError::InvalidManifest(_))
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+
+use super::search::normalize_title;
+use super::{EbiChapter, EbiManga};
+
+// Shared words of both normalized titles, from 0 (none) to 1 (same title)
+pub fn title_similarity(a: &str, b: &str) -> f32 {
+    let a = normalize_title(a);
+    let b = normalize_title(b);
+    if a == b {
+        return 1.0;
+    }
+
+    let a: HashSet<&str> = a.split(' ').filter(|word| !word.is_empty()).collect();
+    let b: HashSet<&str> = b.split(' ').filter(|word| !word.is_empty()).collect();
+    let union = a.union(&b).count();
+    match union {
+        0 => 0.0,
+        _ => a.intersection(&b).count() as f32 / union as f32,
+    }
+}
+
+// Manga of the target source that may be the migrated one
+#[derive(Clone, Debug)]
+pub struct MigrationCandidate {
+    pub manga: EbiManga,
+    pub similarity: f32,
+}
+
+impl MigrationCandidate {
+    // Most similar titles first, keeping the source order between equal ones
+    pub(crate) fn ranked(manga: &EbiManga, found: Vec<EbiManga>) -> Vec<Self> {
+        let mut candidates = found
+            .into_iter()
+            .map(|candidate| Self {
+                similarity: title_similarity(&manga.title, &candidate.title),
+                manga: candidate,
+            })
+            .collect::<Vec<Self>>();
+        candidates.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        candidates
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChapterMapping {
+    pub from: EbiChapter,
+    // None when the target source has no chapter with that number
+    pub to: Option<EbiChapter>,
+}
+
+// Chapters of a manga matched, by number, with the ones of the same manga in another
+// source. Read progress and categories are not stored by ebi: the frontend keeps them
+// and moves them with `target_chapter`. Downloads are moved by
+// SourceManager::migrate_downloads
+#[derive(Clone, Debug)]
+pub struct Migration {
+    pub from: EbiManga,
+    pub to: EbiManga,
+    pub chapters: Vec<ChapterMapping>,
+}
+
+impl Migration {
+    // Chapters in the same language are preferred, chapters without a language match
+    // any of them
+    pub fn new(
+        from: EbiManga,
+        from_chapters: Vec<EbiChapter>,
+        to: EbiManga,
+        to_chapters: &[EbiChapter],
+    ) -> Self {
+        let chapters = from_chapters
+            .into_iter()
+            .map(|chapter| {
+                let same_number = || {
+                    to_chapters
+                        .iter()
+                        .filter(|target| target.chapter == chapter.chapter)
+                };
+                let to = same_number()
+                    .find(|target| target.language == chapter.language)
+                    .or_else(|| {
+                        same_number().find(|target| {
+                            target.language.is_unknown()
+                                || chapter.language.is_unknown()
+                                || target.language.matches(&chapter.language)
+                        })
+                    })
+                    .cloned();
+
+                ChapterMapping { from: chapter, to }
+            })
+            .collect();
+
+        Self { from, to, chapters }
+    }
+
+    pub fn target_chapter(&self, chapter: &EbiChapter) -> Option<&EbiChapter> {
+        self.chapters
+            .iter()
+            .find(|mapping| {
+                mapping.from.chapter == chapter.chapter && mapping.from.language == chapter.language
+            })
+            .and_then(|mapping| mapping.to.as_ref())
+    }
+
+    // Chapters the target source does not have, their progress can't be moved
+    pub fn unmatched(&self) -> Vec<&EbiChapter> {
+        self.chapters
+            .iter()
+            .filter(|mapping| mapping.to.is_none())
+            .map(|mapping| &mapping.from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::Locale;
+
+    fn manga(source: &str, title: &str) -> EbiManga {
+        EbiManga {
+            identifier: title.to_lowercase(),
+            title: title.to_owned(),
+            cover: String::new(),
+            url: String::new(),
+            genres: Vec::new(),
+            description: None,
+            source: source.to_owned(),
+        }
+    }
+
+    fn chapter(source: &str, chapter: f32, language: Locale) -> EbiChapter {
+        EbiChapter {
+            chapter,
+            title: String::new(),
+            url: format!("{}/{}/{}", source, chapter, language),
+            manga: "manga".to_owned(),
+            source: source.to_owned(),
+            language,
+        }
+    }
+
+    #[test]
+    fn title_similarity_compares_normalized_words() {
+        assert_eq!(title_similarity("One Piece!", "one  piece"), 1.0);
+        assert_eq!(title_similarity("One Piece", "Naruto"), 0.0);
+        assert_eq!(title_similarity("One Piece", "One Piece Party"), 2.0 / 3.0);
+        assert_eq!(title_similarity("a b", "b c"), 1.0 / 3.0);
+        assert_eq!(title_similarity("?!", "..."), 1.0);
+        assert_eq!(title_similarity("", "One Piece"), 0.0);
+    }
+
+    #[test]
+    fn candidates_are_ranked_by_similarity() {
+        let candidates = MigrationCandidate::ranked(
+            &manga("a", "One Piece"),
+            vec![
+                manga("b", "Naruto"),
+                manga("b", "One Piece Party"),
+                manga("b", "ONE PIECE"),
+            ],
+        );
+        let titles: Vec<&str> = candidates.iter().map(|c| c.manga.title.as_str()).collect();
+        assert_eq!(titles, ["ONE PIECE", "One Piece Party", "Naruto"]);
+    }
+
+    #[test]
+    fn chapters_are_matched_by_number_and_language() {
+        let from = vec![
+            chapter("a", 1.0, Locale::EN),
+            chapter("a", 1.0, Locale::PT_BR),
+            chapter("a", 2.0, Locale::EN),
+            chapter("a", 10.5, Locale::UNKNOWN),
+            chapter("a", 11.0, Locale::EN),
+        ];
+        let to = [
+            chapter("b", 1.0, Locale::PT_BR),
+            chapter("b", 1.0, Locale::EN),
+            chapter("b", 2.0, Locale::EN_US),
+            chapter("b", 10.0, Locale::EN),
+            chapter("b", 10.5, Locale::EN),
+        ];
+        let migration = Migration::new(manga("a", "Manga"), from.clone(), manga("b", "Manga"), &to);
+
+        let target = |chapter: &EbiChapter| migration.target_chapter(chapter).map(|c| &c.url);
+        // Same language first, whatever the order of the target chapters
+        assert_eq!(target(&from[0]), Some(&to[1].url));
+        assert_eq!(target(&from[1]), Some(&to[0].url));
+        // Else a matching one
+        assert_eq!(target(&from[2]), Some(&to[2].url));
+        // Fractional numbers are kept apart from whole ones
+        assert_eq!(target(&from[3]), Some(&to[4].url));
+        assert_eq!(target(&from[4]), None);
+
+        let unmatched: Vec<f32> = migration.unmatched().iter().map(|c| c.chapter).collect();
+        assert_eq!(unmatched, [11.0]);
+    }
+}
@@ -1,8 +1,89 @@
 pub mod archive;
+#[cfg(feature = "async")]
+pub mod async_manager;
+pub mod builder;
+pub(crate) mod cache;
+pub(crate) mod cookies;
+pub(crate) mod credentials;
 pub(crate) mod loader;
 pub mod manager;
+pub(crate) mod manifest;
+pub mod migration;
+pub(crate) mod preferences;
+pub mod report;
+pub mod repository;
+pub mod search;
+pub(crate) mod signature;
+#[cfg(feature = "watcher")]
+pub(crate) mod watcher;
 
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::EbiError;
+
+pub use ebi_source::cookies::Cookie;
+pub use ebi_source::credentials::Credentials;
+pub use ebi_source::locale::Locale;
+pub use ebi_source::manifest::{SourceCapabilities, SourceManifest};
+pub use ebi_source::preferences::{
+    Preference, PreferenceKind, PreferenceSchema, PreferenceValue, Preferences,
+};
 pub use ebi_source::SourceLoader;
 pub use ebi_source::{Chapter as EbiChapter, Manga as EbiManga, Source as EbiSource};
 
+#[cfg(feature = "async")]
+pub use async_manager::{AsyncGlobalSearch, AsyncSourceManager};
+pub use builder::SourceManagerBuilder;
+pub use cache::CacheOperation;
+pub use credentials::CredentialKey;
+pub use loader::SourceFunctions;
 pub use manager::SourceManager;
+pub use migration::{ChapterMapping, Migration, MigrationCandidate};
+pub use report::{LoadOutcome, LoadReport, ManifestReport};
+pub use search::{GlobalSearch, SearchGroup, SearchGroups, SourceSearchResult};
+
+// Poison tolerant, so a panic while a lock was held (e.g.: in a source callback) doesn't
+// make every later call panic too
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Identifiers name the files and directories kept for a source: ASCII letters, digits,
+// `_`, `-` and `.`, not starting with a dot
+pub(crate) fn validate_identifier(identifier: &str) -> Result<(), EbiError> {
+    let valid = !identifier.starts_with('.')
+        && !identifier.is_empty()
+        && identifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    match valid {
+        true => Ok(()),
+        false => Err(EbiError::InvalidIdentifier(identifier.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_can_not_leave_their_directory() {
+        for identifier in ["valid_source_macro_mock", "mangadex-v2", "source.en"] {
+            assert!(validate_identifier(identifier).is_ok());
+        }
+        for identifier in [
+            "",
+            ".",
+            "..",
+            "../config",
+            "a/b",
+            "a\\b",
+            ".hidden",
+            "sourcé",
+        ] {
+            assert!(validate_identifier(identifier).is_err(), "{}", identifier);
+        }
+    }
+}
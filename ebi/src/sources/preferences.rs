@@ -0,0 +1,50 @@
+use std::path::{Path, PathBuf};
+
+use ebi_source::preferences::Preferences;
+
+use crate::error::EbiError;
+
+// <config_dir>/preferences/<identifier>.json
+pub fn preferences_path<P: AsRef<Path>>(config_dir: P, identifier: &str) -> PathBuf {
+    let mut path = config_dir.as_ref().to_path_buf();
+    path.push("preferences");
+    path.push(format!("{}.json", identifier));
+    path
+}
+
+// Values stored for a source, empty if it has none
+pub fn read_preferences<P: AsRef<Path>>(
+    config_dir: P,
+    identifier: &str,
+) -> Result<Preferences, EbiError> {
+    let path = preferences_path(config_dir, identifier);
+    if !path.exists() {
+        return Ok(Preferences::default());
+    }
+
+    let preferences = std::fs::read(path)?;
+    serde_json::from_slice(&preferences).map_err(|e| EbiError::InvalidConfig(e.to_string()))
+}
+
+pub fn write_preferences<P: AsRef<Path>>(
+    config_dir: P,
+    identifier: &str,
+    preferences: &Preferences,
+) -> Result<(), EbiError> {
+    let path = preferences_path(config_dir, identifier);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let preferences = serde_json::to_vec_pretty(preferences)
+        .map_err(|e| EbiError::InvalidConfig(e.to_string()))?;
+    std::fs::write(path, preferences).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))
+}
+
+pub fn remove_preferences<P: AsRef<Path>>(config_dir: P, identifier: &str) -> Result<(), EbiError> {
+    let path = preferences_path(config_dir, identifier);
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use ebi_source::manifest::SourceManifest;
+
+use crate::error::EbiError;
+
+#[derive(Debug)]
+pub enum LoadOutcome {
+    Loaded(String),
+    AlreadyLoaded(String),
+    MissingLibrary(PathBuf),
+    AbiMismatch(u32),
+    DuplicatedIdentifier(String),
+    // Refused before the library was loaded: invalid directory, manifest or signature
+    Rejected(EbiError),
+    // The library could not be loaded or its source_info failed
+    InitError(EbiError),
+}
+
+#[derive(Debug)]
+pub struct LoadReportEntry {
+    pub dir: PathBuf,
+    pub outcome: LoadOutcome,
+}
+
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub entries: Vec<LoadReportEntry>,
+}
+
+// Manifests of the installed sources. A manifest that can't be read does not hide the others
+#[derive(Debug, Default)]
+pub struct ManifestReport {
+    pub manifests: Vec<SourceManifest>,
+    // By source directory
+    pub errors: Vec<(PathBuf, EbiError)>,
+}
+
+impl From<EbiError> for LoadOutcome {
+    fn from(error: EbiError) -> Self {
+        match error {
+            EbiError::IncompatibleAbiVersion(version) => Self::AbiMismatch(version),
+            EbiError::DuplicatedSource(identifier) => Self::DuplicatedIdentifier(identifier),
+            EbiError::InvalidDir(_)
+            | EbiError::InvalidManifest(_)
+            | EbiError::UnsignedSource(_)
+            | EbiError::InvalidSignature(_)
+            | EbiError::UntrustedSourceKey(_, _) => Self::Rejected(error),
+            _ => Self::InitError(error),
+        }
+    }
+}
+
+impl LoadReport {
+    pub(crate) fn push(&mut self, dir: PathBuf, outcome: LoadOutcome) {
+        self.entries.push(LoadReportEntry { dir, outcome });
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|entry| match entry.outcome {
+            LoadOutcome::Loaded(ref identifier) => Some(identifier.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &LoadReportEntry> {
+        self.entries.iter().filter(|entry| {
+            !matches!(
+                entry.outcome,
+                LoadOutcome::Loaded(_) | LoadOutcome::AlreadyLoaded(_)
+            )
+        })
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::validate_identifier;
+use crate::{
+    downloader::{fetch, HttpClient},
+    error::EbiError,
+};
+
+const INSTALLED_FILE_NAME: &str = "installed.json";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RepositoryIndex {
+    pub sources: Vec<RepositorySource>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RepositorySource {
+    pub identifier: String,
+    pub version: String,
+    // ABI version the artifacts were built against
+    #[serde(alias = "min_abi_version")]
+    pub abi_version: u32,
+    // Base64 encoded minisign public key used to sign the artifacts
+    #[serde(default)]
+    pub public_key: Option<String>,
+    // Url of the source manifest.json
+    #[serde(default)]
+    pub manifest: Option<String>,
+    // <arch>-<os> (e.g.: x86_64-linux) => artifact
+    pub artifacts: HashMap<String, RepositoryArtifact>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RepositoryArtifact {
+    pub url: String,
+    pub sha256: String,
+    // Detached minisign signature of the artifact
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+// Stored next to each installed source, so updates can be detected
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InstalledSource {
+    pub identifier: String,
+    pub version: String,
+    pub sha256: String,
+}
+
+// file:// urls, read from disk by `fetch`
+fn is_local(url: &str) -> bool {
+    url.get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("file://"))
+}
+
+pub fn host_target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+impl RepositoryIndex {
+    pub fn fetch(http: &HttpClient, index_url: &str) -> Result<Self, EbiError> {
+        let index = fetch(http, index_url)?;
+        let mut index: Self = serde_json::from_slice(&index)
+            .map_err(|e| EbiError::InvalidRepositoryIndex(e.to_string()))?;
+
+        // Artifact urls without a scheme are relative to the index location
+        let base_url = match index_url.rfind('/') {
+            Some(idx) => &index_url[..=idx],
+            None => "",
+        };
+        let resolve = |url: &mut String| {
+            if !url.contains("://") {
+                *url = format!("{}{}", base_url, url);
+            }
+        };
+        for artifact in index
+            .sources
+            .iter_mut()
+            .flat_map(|s| s.artifacts.values_mut())
+        {
+            resolve(&mut artifact.url);
+            if let Some(ref mut signature) = artifact.signature {
+                resolve(signature);
+            }
+        }
+        for manifest in index.sources.iter_mut().filter_map(|s| s.manifest.as_mut()) {
+            resolve(manifest);
+        }
+
+        // Identifiers name the installed source directory
+        for source in index.sources.iter() {
+            validate_identifier(&source.identifier)?;
+        }
+        // Only an index read from disk may point to local files
+        if !is_local(index_url) {
+            index.reject_local_urls()?;
+        }
+
+        Ok(index)
+    }
+
+    fn reject_local_urls(&self) -> Result<(), EbiError> {
+        match self
+            .sources
+            .iter()
+            .find(|source| source.urls().any(is_local))
+        {
+            Some(source) => Err(EbiError::InvalidRepositoryIndex(format!(
+                "{} points to a local file",
+                source.identifier
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn source(&self, identifier: &str) -> Result<&RepositorySource, EbiError> {
+        self.sources
+            .iter()
+            .find(|s| s.identifier == identifier)
+            .ok_or_else(|| EbiError::SourceNotInRepository(identifier.to_owned()))
+    }
+}
+
+impl RepositorySource {
+    // Every url fetched to install the source
+    fn urls(&self) -> impl Iterator<Item = &str> {
+        let artifacts = self.artifacts.values().flat_map(|artifact| {
+            std::iter::once(artifact.url.as_str()).chain(artifact.signature.as_deref())
+        });
+        artifacts.chain(self.manifest.as_deref())
+    }
+
+    pub fn host_artifact(&self) -> Result<&RepositoryArtifact, EbiError> {
+        if !ebi_source::abi::is_compatible(self.abi_version) {
+            return Err(EbiError::IncompatibleAbiVersion(self.abi_version));
+        }
+
+        let target = host_target();
+        self.artifacts
+            .get(&target)
+            .ok_or(EbiError::UnsupportedTarget(target))
+    }
+}
+
+impl RepositoryArtifact {
+    pub fn download(&self, http: &HttpClient) -> Result<Vec<u8>, EbiError> {
+        let artifact = fetch(http, &self.url)?;
+
+        let checksum = hex::encode(Sha256::digest(&artifact));
+        if !checksum.eq_ignore_ascii_case(&self.sha256) {
+            return Err(EbiError::ChecksumMismatch(self.url.clone()));
+        }
+
+        Ok(artifact)
+    }
+
+    pub fn download_signature(&self, http: &HttpClient) -> Result<Option<Vec<u8>>, EbiError> {
+        match self.signature {
+            Some(ref signature) => Ok(Some(fetch(http, signature)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl InstalledSource {
+    pub fn read<P: AsRef<Path>>(source_dir: P) -> Option<Self> {
+        let mut path = source_dir.as_ref().to_path_buf();
+        path.push(INSTALLED_FILE_NAME);
+
+        let installed = std::fs::read(path).ok()?;
+        serde_json::from_slice(&installed).ok()
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, source_dir: P) -> Result<(), EbiError> {
+        let mut path = source_dir.as_ref().to_path_buf();
+        path.push(INSTALLED_FILE_NAME);
+
+        let installed =
+            serde_json::to_vec_pretty(self).map_err(|e| EbiError::Unknown(e.to_string()))?;
+        std::fs::write(path, installed).map_err(|e| EbiError::CouldNotSaveFile(e.to_string()))
+    }
+
+    pub fn remove<P: AsRef<Path>>(source_dir: P) -> Result<(), EbiError> {
+        let mut path = source_dir.as_ref().to_path_buf();
+        path.push(INSTALLED_FILE_NAME);
+
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r#"{
+        "sources": [{
+            "identifier": "example",
+            "version": "1.0.0",
+            "abi_version": 1,
+            "manifest": "example/manifest.json",
+            "artifacts": {
+                "x86_64-linux": {
+                    "url": "example/libexample.so",
+                    "sha256": "00",
+                    "signature": "https://cdn.example.org/libexample.so.minisig"
+                }
+            }
+        }]
+    }"#;
+
+    fn fetch_index() -> RepositoryIndex {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        std::fs::write(&path, INDEX).unwrap();
+
+        let url = format!("file://{}", path.display());
+        RepositoryIndex::fetch(&HttpClient::default(), &url).unwrap()
+    }
+
+    #[test]
+    fn relative_urls_are_resolved_against_the_index() {
+        let index = fetch_index();
+        let source = index.source("example").unwrap();
+        let manifest = source.manifest.as_deref().unwrap();
+        assert!(manifest.starts_with("file://") && manifest.ends_with("/example/manifest.json"));
+
+        let artifact = &source.artifacts["x86_64-linux"];
+        assert!(artifact.url.starts_with("file://"));
+        assert!(artifact.url.ends_with("/example/libexample.so"));
+        assert_eq!(
+            artifact.signature.as_deref(),
+            Some("https://cdn.example.org/libexample.so.minisig")
+        );
+    }
+
+    #[test]
+    fn identifiers_are_validated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+        std::fs::write(&path, INDEX.replace("\"example\"", "\"../../example\"")).unwrap();
+
+        let url = format!("file://{}", path.display());
+        assert!(matches!(
+            RepositoryIndex::fetch(&HttpClient::default(), &url),
+            Err(EbiError::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn remote_indexes_can_not_point_to_local_files() {
+        let mut index = fetch_index();
+        assert!(matches!(
+            index.reject_local_urls(),
+            Err(EbiError::InvalidRepositoryIndex(_))
+        ));
+
+        let artifact = index.sources[0].artifacts.get_mut("x86_64-linux").unwrap();
+        artifact.url = "https://cdn.example.org/libexample.so".to_owned();
+        index.sources[0].manifest = Some("FILE:///etc/passwd".to_owned());
+        assert!(index.reject_local_urls().is_err());
+
+        index.sources[0].manifest = Some("https://cdn.example.org/manifest.json".to_owned());
+        assert!(index.reject_local_urls().is_ok());
+    }
+
+    #[test]
+    fn unknown_sources_are_not_in_the_repository() {
+        let index = fetch_index();
+        assert!(matches!(
+            index.source("missing"),
+            Err(EbiError::SourceNotInRepository(_))
+        ));
+    }
+
+    #[test]
+    fn other_abi_versions_are_incompatible() {
+        let mut index = fetch_index();
+        for abi_version in [
+            ebi_source::abi::ABI_VERSION - 1,
+            ebi_source::abi::ABI_VERSION + 1,
+        ] {
+            index.sources[0].abi_version = abi_version;
+            assert!(matches!(
+                index.sources[0].host_artifact(),
+                Err(EbiError::IncompatibleAbiVersion(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn min_abi_version_is_still_read() {
+        let index: RepositoryIndex =
+            serde_json::from_str(&INDEX.replace("\"abi_version\"", "\"min_abi_version\"")).unwrap();
+        assert_eq!(index.sources[0].abi_version, 1);
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::error::EbiError;
+
+use super::{lock, EbiManga};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+// Threads of a SearchPool
+const SEARCH_THREADS: usize = 8;
+
+// Answer of a single source to a global search
+#[derive(Debug)]
+pub struct SourceSearchResult {
+    pub source: String,
+    pub result: Result<Vec<EbiManga>, EbiError>,
+    // Age of the results when they come from the cache instead of the source, e.g.:
+    // offline, or stale ones being refreshed in the background
+    pub cached: Option<Duration>,
+}
+
+impl SourceSearchResult {
+    pub(crate) fn new(
+        source: String,
+        result: Result<(Vec<EbiManga>, Option<Duration>), EbiError>,
+    ) -> Self {
+        let (result, cached) = match result {
+            Ok((manga, cached)) => (Ok(manga), cached),
+            Err(e) => (Err(e), None),
+        };
+        Self {
+            source,
+            result,
+            cached,
+        }
+    }
+}
+
+type SearchJob = Box<dyn FnOnce() + Send>;
+
+// At most SEARCH_THREADS threads, started as needed and shared by every global search of
+// a manager. Sources are not cancelled: a timed out search keeps its thread (and its
+// source) until the source returns, and searches queue behind it once every thread is
+// taken. Threads end when the manager is dropped
+pub(crate) struct SearchPool {
+    sender: Mutex<Sender<SearchJob>>,
+    receiver: Arc<Mutex<Receiver<SearchJob>>>,
+    threads: AtomicUsize,
+    // Jobs not started yet, and threads waiting for one
+    queued: Arc<AtomicUsize>,
+    idle: Arc<AtomicUsize>,
+}
+
+impl SearchPool {
+    pub(crate) fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Arc::new(Mutex::new(receiver)),
+            threads: AtomicUsize::new(0),
+            queued: Arc::new(AtomicUsize::new(0)),
+            idle: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub(crate) fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        let sender = lock(&self.sender);
+        // Only fails once every thread is gone, which can't happen while `self` is alive
+        let queued = self.queued.fetch_add(1, Ordering::SeqCst) + 1;
+        let _ = sender.send(Box::new(job));
+
+        if queued > self.idle.load(Ordering::SeqCst) && self.threads() < SEARCH_THREADS {
+            self.threads.fetch_add(1, Ordering::SeqCst);
+            self.spawn();
+        }
+    }
+
+    pub(crate) fn threads(&self) -> usize {
+        self.threads.load(Ordering::SeqCst)
+    }
+
+    fn spawn(&self) {
+        let receiver = self.receiver.clone();
+        let queued = self.queued.clone();
+        let idle = self.idle.clone();
+        idle.fetch_add(1, Ordering::SeqCst);
+
+        std::thread::spawn(move || loop {
+            let job = lock(&receiver).recv();
+            let job = match job {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+
+            queued.fetch_sub(1, Ordering::SeqCst);
+            idle.fetch_sub(1, Ordering::SeqCst);
+            // A panicking source must not take the thread down with it
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+            idle.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+}
+
+// Results of a global search, yielded as each source answers. Sources still searching
+// once the timeout is over are yielded as EbiError::Timeout, their calls keep running in
+// the background (see SearchPool) and their results are discarded
+pub struct GlobalSearch {
+    receiver: Receiver<SourceSearchResult>,
+    pending: Vec<String>,
+    started: Instant,
+    timeout: Duration,
+}
+
+impl GlobalSearch {
+    pub(crate) fn new(receiver: Receiver<SourceSearchResult>, pending: Vec<String>) -> Self {
+        Self {
+            receiver,
+            pending,
+            started: Instant::now(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    // Counted from the start of the search, the same for every source
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Sources that did not answer yet
+    pub fn pending(&self) -> &[String] {
+        &self.pending
+    }
+
+    // Waits for every source
+    pub fn grouped(self) -> SearchGroups {
+        self.collect()
+    }
+}
+
+impl Iterator for GlobalSearch {
+    type Item = SourceSearchResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let remaining = (self.started + self.timeout).saturating_duration_since(Instant::now());
+        match self.receiver.recv_timeout(remaining) {
+            Ok(result) => {
+                self.pending.retain(|source| *source != result.source);
+                Some(result)
+            }
+            Err(e) => {
+                let source = self.pending.remove(0);
+                let error = match e {
+                    RecvTimeoutError::Timeout => EbiError::Timeout(format!("search :: {}", source)),
+                    RecvTimeoutError::Disconnected => {
+                        EbiError::Task(format!("search :: {}", source))
+                    }
+                };
+                Some(SourceSearchResult {
+                    source,
+                    result: Err(error),
+                    cached: None,
+                })
+            }
+        }
+    }
+}
+
+// Lowercase alphanumeric words, so "One Piece!" and "one  piece" are the same series
+pub fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Same series found in one or more sources
+#[derive(Clone, Debug)]
+pub struct SearchGroup {
+    // As given by the first source it was found in
+    pub title: String,
+    pub normalized_title: String,
+    pub manga: Vec<EbiManga>,
+}
+
+impl SearchGroup {
+    pub fn sources(&self) -> Vec<&str> {
+        let mut sources: Vec<&str> = Vec::new();
+        for manga in self.manga.iter() {
+            if !sources.contains(&manga.source.as_str()) {
+                sources.push(&manga.source);
+            }
+        }
+        sources
+    }
+}
+
+// Groups results by normalized title, in the order they were first found. Can be fed
+// while the search is still running, e.g.: to refresh a UI as sources answer
+#[derive(Debug, Default)]
+pub struct SearchGroups {
+    groups: Vec<SearchGroup>,
+    index: HashMap<String, usize>,
+    errors: Vec<(String, EbiError)>,
+    cached: Vec<(String, Duration)>,
+}
+
+impl SearchGroups {
+    pub fn add(&mut self, result: SourceSearchResult) {
+        if let (Ok(_), Some(age)) = (&result.result, result.cached) {
+            self.cached.push((result.source.clone(), age));
+        }
+
+        let manga = match result.result {
+            Ok(manga) => manga,
+            Err(e) => {
+                log::warn!("Could not search {} :: {}", result.source, e);
+                self.errors.push((result.source, e));
+                return;
+            }
+        };
+
+        for manga in manga {
+            let normalized_title = normalize_title(&manga.title);
+            match self.index.get(&normalized_title) {
+                Some(idx) => self.groups[*idx].manga.push(manga),
+                None => {
+                    self.index
+                        .insert(normalized_title.clone(), self.groups.len());
+                    self.groups.push(SearchGroup {
+                        title: manga.title.clone(),
+                        normalized_title,
+                        manga: vec![manga],
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn groups(&self) -> &[SearchGroup] {
+        &self.groups
+    }
+
+    pub fn into_groups(self) -> Vec<SearchGroup> {
+        self.groups
+    }
+
+    // Sources that failed or timed out
+    pub fn errors(&self) -> &[(String, EbiError)] {
+        &self.errors
+    }
+
+    // Sources whose results come from the cache, with their age
+    pub fn cached(&self) -> &[(String, Duration)] {
+        &self.cached
+    }
+}
+
+impl FromIterator<SourceSearchResult> for SearchGroups {
+    fn from_iter<I: IntoIterator<Item = SourceSearchResult>>(iter: I) -> Self {
+        let mut groups = Self::default();
+        iter.into_iter().for_each(|result| groups.add(result));
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manga(source: &str, title: &str) -> EbiManga {
+        EbiManga {
+            identifier: title.to_owned(),
+            title: title.to_owned(),
+            cover: String::new(),
+            url: String::new(),
+            genres: Vec::new(),
+            description: None,
+            source: source.to_owned(),
+        }
+    }
+
+    fn result(source: &str, titles: &[&str], cached: Option<Duration>) -> SourceSearchResult {
+        let manga = titles.iter().map(|title| manga(source, title)).collect();
+        SourceSearchResult::new(source.to_owned(), Ok((manga, cached)))
+    }
+
+    #[test]
+    fn normalizes_titles() {
+        assert_eq!(normalize_title("One Piece!"), "one piece");
+        assert_eq!(normalize_title("  one   piece "), "one piece");
+        assert_eq!(
+            normalize_title("Re:Zero - Starting Life"),
+            "re zero starting life"
+        );
+        assert_eq!(normalize_title("ÉPÉE & Co."), "épée co");
+        assert_eq!(normalize_title("進撃の巨人"), "進撃の巨人");
+        assert_eq!(normalize_title("?!"), "");
+    }
+
+    #[test]
+    fn groups_by_normalized_title_in_order() {
+        let groups: SearchGroups = [
+            result("b", &["One Piece!", "Naruto"], None),
+            result("a", &["one piece", "Bleach"], Some(Duration::from_secs(60))),
+            result("a", &["ONE PIECE"], None),
+            SourceSearchResult::new("c".to_owned(), Err(EbiError::InvalidSource)),
+        ]
+        .into_iter()
+        .collect();
+
+        let titles: Vec<&str> = groups.groups().iter().map(|g| g.title.as_str()).collect();
+        assert_eq!(titles, ["One Piece!", "Naruto", "Bleach"]);
+
+        let one_piece = &groups.groups()[0];
+        assert_eq!(one_piece.normalized_title, "one piece");
+        assert_eq!(one_piece.manga.len(), 3);
+        assert_eq!(one_piece.sources(), ["b", "a"]);
+
+        assert_eq!(groups.errors().len(), 1);
+        assert_eq!(groups.errors()[0].0, "c");
+        assert_eq!(groups.cached(), [("a".to_owned(), Duration::from_secs(60))]);
+    }
+
+    #[test]
+    fn pending_sources_time_out() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(result("a", &["Naruto"], None)).unwrap();
+        let search = GlobalSearch::new(receiver, vec!["a".to_owned(), "b".to_owned()])
+            .with_timeout(Duration::from_millis(50));
+
+        let results: Vec<SourceSearchResult> = search.collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].result.is_ok());
+        assert_eq!(results[1].source, "b");
+        assert!(matches!(results[1].result, Err(EbiError::Timeout(_))));
+        drop(sender);
+    }
+
+    #[test]
+    fn pool_is_bounded_and_runs_every_job() {
+        let pool = SearchPool::new();
+        let (sender, receiver) = mpsc::channel();
+        let (release, blocked) = mpsc::channel::<()>();
+        let blocked = Arc::new(Mutex::new(blocked));
+
+        // Every thread taken by jobs that never return on their own
+        for _ in 0..SEARCH_THREADS {
+            let blocked = blocked.clone();
+            pool.execute(move || {
+                let _ = blocked.lock().unwrap().recv();
+            });
+        }
+        for job in 0..4 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(job).unwrap());
+        }
+        assert!(pool.threads() <= SEARCH_THREADS);
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+
+        // Queued jobs run once threads are free
+        for _ in 0..SEARCH_THREADS {
+            release.send(()).unwrap();
+        }
+        let mut jobs: Vec<i32> = (0..4)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        jobs.sort();
+        assert_eq!(jobs, [0, 1, 2, 3]);
+        assert_eq!(pool.threads(), SEARCH_THREADS);
+    }
+}
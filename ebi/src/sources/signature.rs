@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use minisign_verify::{PublicKey, Signature};
+
+use crate::{config::SignaturePolicy, error::EbiError};
+
+const SIGNATURE_FILE_EXTENSION: &str = "minisig";
+pub(crate) const PUBLIC_KEY_FILE_NAME: &str = "minisign.pub";
+
+// <lib_path>.minisig
+pub(crate) fn signature_path(lib_path: &Path) -> PathBuf {
+    let mut path = lib_path.as_os_str().to_owned();
+    path.push(format!(".{}", SIGNATURE_FILE_EXTENSION));
+    PathBuf::from(path)
+}
+
+// Must be called before the library is loaded, as loading it already runs plugin code.
+// Returns the verified bytes, which are the ones to load: the file itself may have
+// changed since it was read
+pub(crate) fn verify_source(
+    identifier: &str,
+    lib_path: &Path,
+    policy: SignaturePolicy,
+    trusted_keys: &[String],
+) -> Result<Vec<u8>, EbiError> {
+    let lib = std::fs::read(lib_path)?;
+
+    let signature_path = signature_path(lib_path);
+    if !signature_path.exists() {
+        return match policy {
+            SignaturePolicy::AllowUnsigned => {
+                log::warn!("Loading unsigned source {}", identifier);
+                Ok(lib)
+            }
+            SignaturePolicy::RequireSigned => Err(EbiError::UnsignedSource(identifier.to_owned())),
+        };
+    }
+
+    let signature = Signature::from_file(&signature_path)
+        .map_err(|_| EbiError::InvalidSignature(identifier.to_owned()))?;
+
+    let trusted = trusted_keys
+        .iter()
+        .filter_map(|key| PublicKey::from_base64(key).ok())
+        .any(|key| key.verify(&lib, &signature, false).is_ok());
+    if trusted {
+        return Ok(lib);
+    }
+
+    // Trust on first use: a signature made with the key shipped alongside the source
+    // is surfaced, so the user can decide whether to trust it
+    let mut public_key_path = lib_path.to_path_buf();
+    public_key_path.set_file_name(PUBLIC_KEY_FILE_NAME);
+
+    let public_key = std::fs::read_to_string(public_key_path).ok();
+    let public_key = public_key.as_deref().and_then(|public_key| {
+        let key = PublicKey::decode(public_key).ok()?;
+        key.verify(&lib, &signature, false).ok()?;
+        public_key.lines().nth(1).map(|key| key.trim().to_owned())
+    });
+
+    match public_key {
+        Some(public_key) => Err(EbiError::UntrustedSourceKey(
+            identifier.to_owned(),
+            public_key,
+        )),
+        None => Err(EbiError::InvalidSignature(identifier.to_owned())),
+    }
+}
+
+pub(crate) fn validate_public_key(public_key: &str) -> Result<(), EbiError> {
+    PublicKey::from_base64(public_key)
+        .map(|_| ())
+        .map_err(|_| EbiError::InvalidPublicKey(public_key.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unsigned_lib() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let lib_path = dir.path().join("libexample.so");
+        std::fs::write(&lib_path, b"library").unwrap();
+        (dir, lib_path)
+    }
+
+    #[test]
+    fn unsigned_sources_follow_the_policy() {
+        let (_dir, lib_path) = unsigned_lib();
+
+        let lib = verify_source("example", &lib_path, SignaturePolicy::AllowUnsigned, &[]);
+        assert_eq!(lib.unwrap(), b"library");
+
+        let lib = verify_source("example", &lib_path, SignaturePolicy::RequireSigned, &[]);
+        assert!(matches!(lib, Err(EbiError::UnsignedSource(_))));
+    }
+
+    #[test]
+    fn invalid_signatures_are_refused_whatever_the_policy() {
+        let (_dir, lib_path) = unsigned_lib();
+        std::fs::write(signature_path(&lib_path), b"not a signature").unwrap();
+
+        let lib = verify_source("example", &lib_path, SignaturePolicy::AllowUnsigned, &[]);
+        assert!(matches!(lib, Err(EbiError::InvalidSignature(_))));
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    mpsc::{channel, Receiver},
+    Mutex,
+};
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::error::EbiError;
+
+use super::lock;
+use super::manager::handle_source_file_extension;
+
+// A library is only reported once it stopped changing for this long, so a build still
+// writing it is not loaded half written
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Only collects filesystem events: sources are reloaded by the SourceManager owner,
+// which guarantees there are no outstanding calls into the libraries being replaced
+pub(crate) struct SourceWatcher {
+    _watcher: RecommendedWatcher,
+    source_dir: PathBuf,
+    // Behind a mutex so the SourceManager can be shared between threads
+    events: Mutex<Receiver<notify::Result<Event>>>,
+    // Last change of each library not reported yet
+    pending: Mutex<HashMap<String, Instant>>,
+}
+
+impl SourceWatcher {
+    pub fn new(source_dir: PathBuf) -> Result<Self, EbiError> {
+        let (tx, events) = channel();
+
+        let mut watcher =
+            notify::recommended_watcher(tx).map_err(|e| EbiError::Watcher(e.to_string()))?;
+        watcher
+            .watch(&source_dir, RecursiveMode::Recursive)
+            .map_err(|e| EbiError::Watcher(e.to_string()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            source_dir,
+            events: Mutex::new(events),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Identifiers of the sources whose library changed since the last call, and did not
+    // change again for DEBOUNCE
+    pub fn changed_sources(&self) -> HashSet<String> {
+        let events = lock(&self.events);
+        let mut pending = lock(&self.pending);
+
+        let now = Instant::now();
+        let changed = events
+            .try_iter()
+            .flatten()
+            .filter(|event| matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)))
+            .flat_map(|event| event.paths)
+            .filter_map(|path| self.source_identifier(&path));
+        for identifier in changed {
+            pending.insert(identifier, now);
+        }
+
+        let settled = pending
+            .iter()
+            .filter(|(_, changed_at)| now.duration_since(**changed_at) >= DEBOUNCE)
+            .map(|(identifier, _)| identifier.clone())
+            .collect::<HashSet<String>>();
+        pending.retain(|identifier, _| !settled.contains(identifier));
+        settled
+    }
+
+    // <self.source_dir>/<identifier>/<library file>
+    fn source_identifier(&self, path: &Path) -> Option<String> {
+        let mut components = path.strip_prefix(&self.source_dir).ok()?.components();
+
+        let identifier = components.next()?.as_os_str().to_str()?;
+        let file = components.next()?.as_os_str();
+        if components.next().is_some() || file != handle_source_file_extension(identifier) {
+            return None;
+        }
+
+        Some(identifier.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_reported_once_the_library_settled() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let dir = source_dir.path().join("example");
+        std::fs::create_dir(&dir).unwrap();
+
+        let watcher = SourceWatcher::new(source_dir.path().to_path_buf()).unwrap();
+        std::fs::write(dir.join(handle_source_file_extension("example")), b"lib").unwrap();
+        std::fs::write(dir.join("manifest.json"), b"{}").unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(watcher.changed_sources().is_empty());
+
+        std::thread::sleep(DEBOUNCE);
+        let changed = watcher.changed_sources();
+        assert_eq!(changed, HashSet::from(["example".to_owned()]));
+        assert!(watcher.changed_sources().is_empty());
+    }
+}
@@ -1,16 +1,17 @@
 use std::convert::From;
 
-use crate::{error::SourceError, Chapter};
+use crate::{error::SourceError, locale::Locale, Chapter};
 
-use super::primitives::FFIString;
+use super::{locale::ABILocale, primitives::FFIString};
 
 #[repr(C)]
 pub struct ABIChapter {
-    pub chapter: u32,
+    pub chapter: f32,
     pub title: FFIString,
     pub url: FFIString,
     pub manga: FFIString,
     pub source: FFIString,
+    pub language: ABILocale,
 }
 
 impl From<&Chapter> for ABIChapter {
@@ -26,6 +27,7 @@ impl From<&Chapter> for ABIChapter {
             url,
             manga,
             source,
+            language: ABILocale::from(chapter.language),
         }
     }
 }
@@ -38,6 +40,7 @@ impl TryInto<Chapter> for ABIChapter {
         let url = self.url.try_into()?;
         let manga = self.manga.try_into()?;
         let source = self.source.try_into()?;
+        let language = Locale::try_from(self.language)?;
 
         Ok(Chapter {
             chapter: self.chapter,
@@ -45,6 +48,7 @@ impl TryInto<Chapter> for ABIChapter {
             url,
             manga,
             source,
+            language,
         })
     }
 }
@@ -52,38 +56,64 @@ impl TryInto<Chapter> for ABIChapter {
 pub mod chapter_list {
     use std::convert::From;
 
-    use crate::abi::primitives::{ABIResultArray, FFIString};
+    use crate::abi::future::FFIFuture;
+    use crate::abi::lifecycle::ABIStateHandle;
+    use crate::abi::locale::ABILocale;
+    use crate::abi::primitives::{ABIResultArray, FFIArray, FFIString};
+    use crate::error::SourceError;
+    use crate::locale::Locale;
     use crate::Manga;
 
-    pub type ChapterListFn = extern "C" fn(ABIChapterListInput) -> ABIResultArray;
+    pub type ChapterListFn = extern "C" fn(ABIStateHandle, ABIChapterListInput) -> ABIResultArray;
+    pub type ChapterListAsyncFn =
+        extern "C" fn(ABIStateHandle, ABIChapterListInput) -> FFIFuture<ABIResultArray>;
 
     #[repr(C)]
     pub struct ABIChapterListInput {
         pub identifier: FFIString,
         pub url: FFIString,
+        // Vec<ABILocale>, preferred languages (every language when empty)
+        pub languages: FFIArray,
     }
 
-    impl From<&Manga> for ABIChapterListInput {
-        fn from(manga: &Manga) -> Self {
+    impl ABIChapterListInput {
+        pub fn new(manga: &Manga, languages: &[Locale]) -> Self {
+            let languages = languages
+                .iter()
+                .map(|language| ABILocale::from(*language))
+                .collect::<Vec<ABILocale>>();
+
             Self {
                 identifier: FFIString::from(manga.identifier.clone()),
                 url: FFIString::from(manga.url.clone()),
+                languages: FFIArray::from(languages),
             }
         }
     }
+
+    // An invalid locale is an error, as in ABISource and ABIChapter
+    pub fn parse_languages(languages: FFIArray) -> Result<Vec<Locale>, SourceError> {
+        let languages: Vec<ABILocale> = languages.try_into()?;
+        languages.into_iter().map(Locale::try_from).collect()
+    }
 }
 
 pub mod chapter_page_list {
     use std::convert::From;
 
+    use crate::abi::future::FFIFuture;
+    use crate::abi::lifecycle::ABIStateHandle;
     use crate::abi::primitives::{ABIResultArray, FFIString};
     use crate::Chapter;
 
-    pub type ChapterPageListFn = extern "C" fn(ABIChapterPageListInput) -> ABIResultArray;
+    pub type ChapterPageListFn =
+        extern "C" fn(ABIStateHandle, ABIChapterPageListInput) -> ABIResultArray;
+    pub type ChapterPageListAsyncFn =
+        extern "C" fn(ABIStateHandle, ABIChapterPageListInput) -> FFIFuture<ABIResultArray>;
 
     #[repr(C)]
     pub struct ABIChapterPageListInput {
-        pub chapter: u32,
+        pub chapter: f32,
         pub chapter_url: FFIString,
         pub manga: FFIString,
     }
@@ -98,3 +128,30 @@ pub mod chapter_page_list {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::chapter_list::parse_languages;
+    use crate::abi::{locale::ABILocale, primitives::FFIArray};
+    use crate::{error::SourceError, locale::Locale};
+
+    #[test]
+    fn invalid_languages_are_an_error() {
+        let languages = vec![ABILocale::from(Locale::EN), ABILocale::from(Locale::PT_BR)];
+        assert_eq!(
+            parse_languages(FFIArray::from(languages)).unwrap(),
+            [Locale::EN, Locale::PT_BR]
+        );
+
+        let invalid = ABILocale {
+            language: *b"e1\0",
+            script: [0; 4],
+            region: [0; 3],
+        };
+        let languages = vec![ABILocale::from(Locale::EN), invalid];
+        assert!(matches!(
+            parse_languages(FFIArray::from(languages)),
+            Err(SourceError::InvalidLocale(_))
+        ));
+    }
+}
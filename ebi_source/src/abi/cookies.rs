@@ -0,0 +1,16 @@
+use std::ffi::c_void;
+
+use super::{lifecycle::ABIStateHandle, primitives::FFIString};
+
+// Optional, called right after abi_init with the host cookie store of the source
+pub type SetCookieJarFn = extern "C" fn(ABIStateHandle, ABICookieJar);
+
+// Host callbacks over an opaque store, valid until abi_shutdown
+#[repr(C)]
+pub struct ABICookieJar {
+    pub store: *const c_void,
+    // (store, url) => Cookie header value, null if there is none
+    pub cookie_header: extern "C" fn(*const c_void, FFIString) -> FFIString,
+    // (store, url, Set-Cookie header value)
+    pub set_cookie: extern "C" fn(*const c_void, FFIString, FFIString),
+}
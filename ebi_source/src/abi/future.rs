@@ -0,0 +1,308 @@
+use std::any::Any;
+use std::ffi::c_void;
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Wake, Waker};
+
+use crate::abi::FromSourceError;
+use crate::error::SourceError;
+
+// Wakers can't cross the ABI boundary as they are: the host passes its waker as an
+// opaque pointer plus the functions the source calls to clone/wake/drop it
+#[repr(C)]
+pub struct FFIWakerVTable {
+    pub clone: extern "C" fn(*const c_void) -> *const c_void,
+    pub wake: extern "C" fn(*const c_void),
+    pub wake_by_ref: extern "C" fn(*const c_void),
+    pub drop: extern "C" fn(*const c_void),
+}
+
+#[repr(C)]
+pub struct FFIContext {
+    waker: *const c_void,
+    vtable: &'static FFIWakerVTable,
+}
+
+#[repr(C, u8)]
+pub enum FFIPoll<T> {
+    Ready(T),
+    Pending,
+}
+
+// Future returned by the `abi_*_async` functions. Must not outlive the library it came from.
+//
+// It is polled by the host executor, but the source's copy of any runtime (e.g.: tokio's
+// thread locals) is not the host one: source futures must be runtime-agnostic, and can't
+// rely on tokio I/O, timers or `tokio::spawn`. Their blocking counterparts are driven by
+// `block_on`, which provides no reactor either. A panic while polling (e.g.: "there is
+// no reactor running") resolves the future to SourceError::Panicked instead of aborting
+#[repr(C)]
+pub struct FFIFuture<T> {
+    future: *mut c_void,
+    poll: extern "C" fn(*mut c_void, *mut FFIContext) -> FFIPoll<T>,
+    drop: extern "C" fn(*mut c_void),
+}
+
+// Only built from Send futures
+unsafe impl<T: Send> Send for FFIFuture<T> {}
+
+type BoxedFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_owned(),
+        },
+    }
+}
+
+fn panicked<T: FromSourceError>(payload: Box<dyn Any + Send>) -> T {
+    T::from_source_error(SourceError::Panicked(panic_message(payload)))
+}
+
+// Runs a source function within its `extern "C"` wrapper, as unwinding out of it
+// aborts the host
+pub fn catch_panic<T: FromSourceError>(call: impl FnOnce() -> T) -> T {
+    std::panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(panicked)
+}
+
+// Same as catch_panic, for wrappers without a SourceError to return: `on_panic` gets
+// the panic message
+pub fn catch_panic_with<T>(call: impl FnOnce() -> T, on_panic: impl FnOnce(String) -> T) -> T {
+    std::panic::catch_unwind(AssertUnwindSafe(call))
+        .unwrap_or_else(|payload| on_panic(panic_message(payload)))
+}
+
+// Source side
+impl<T: FromSourceError + 'static> FFIFuture<T> {
+    pub fn new<F>(future: F) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let future: Box<BoxedFuture<T>> = Box::new(Box::pin(future));
+
+        Self {
+            future: Box::into_raw(future) as *mut c_void,
+            poll: poll_boxed::<T>,
+            drop: drop_boxed::<T>,
+        }
+    }
+}
+
+extern "C" fn poll_boxed<T: FromSourceError>(
+    future: *mut c_void,
+    cx: *mut FFIContext,
+) -> FFIPoll<T> {
+    let future = unsafe { &mut *(future as *mut BoxedFuture<T>) };
+    let cx = unsafe { &*cx };
+
+    // Borrowed for this poll only, as the host waker it points to is
+    let waker = FFIWaker {
+        waker: cx.waker,
+        vtable: cx.vtable,
+    };
+    let raw_waker = RawWaker::new(
+        &waker as *const FFIWaker as *const (),
+        &BORROWED_WAKER_VTABLE,
+    );
+    let waker = ManuallyDrop::new(unsafe { Waker::from_raw(raw_waker) });
+
+    // The host stops polling once Ready, so a future that panicked is never polled again
+    let poll = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        future.as_mut().poll(&mut Context::from_waker(&waker))
+    }));
+    match poll {
+        Ok(Poll::Ready(value)) => FFIPoll::Ready(value),
+        Ok(Poll::Pending) => FFIPoll::Pending,
+        Err(payload) => FFIPoll::Ready(panicked(payload)),
+    }
+}
+
+// Arguments that could not be read resolve the future right away
+impl<T: FromSourceError + 'static> FromSourceError for FFIFuture<T> {
+    fn from_source_error(error: SourceError) -> Self {
+        Self::new(async move { T::from_source_error(error) })
+    }
+}
+
+extern "C" fn drop_boxed<T>(future: *mut c_void) {
+    drop(unsafe { Box::from_raw(future as *mut BoxedFuture<T>) });
+}
+
+// Waker created by the source out of the host one
+struct FFIWaker {
+    waker: *const c_void,
+    vtable: &'static FFIWakerVTable,
+}
+
+static BORROWED_WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    clone_ffi_waker,
+    wake_borrowed_ffi_waker,
+    wake_by_ref_ffi_waker,
+    drop_borrowed_ffi_waker,
+);
+
+static OWNED_WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    clone_ffi_waker,
+    wake_owned_ffi_waker,
+    wake_by_ref_ffi_waker,
+    drop_owned_ffi_waker,
+);
+
+unsafe fn clone_ffi_waker(waker: *const ()) -> RawWaker {
+    let waker = &*(waker as *const FFIWaker);
+    let cloned = Box::new(FFIWaker {
+        waker: (waker.vtable.clone)(waker.waker),
+        vtable: waker.vtable,
+    });
+    RawWaker::new(Box::into_raw(cloned) as *const (), &OWNED_WAKER_VTABLE)
+}
+
+unsafe fn wake_borrowed_ffi_waker(waker: *const ()) {
+    wake_by_ref_ffi_waker(waker)
+}
+
+unsafe fn wake_owned_ffi_waker(waker: *const ()) {
+    let waker = Box::from_raw(waker as *mut FFIWaker);
+    (waker.vtable.wake)(waker.waker);
+}
+
+unsafe fn wake_by_ref_ffi_waker(waker: *const ()) {
+    let waker = &*(waker as *const FFIWaker);
+    (waker.vtable.wake_by_ref)(waker.waker);
+}
+
+unsafe fn drop_borrowed_ffi_waker(_: *const ()) {}
+
+unsafe fn drop_owned_ffi_waker(waker: *const ()) {
+    let waker = Box::from_raw(waker as *mut FFIWaker);
+    (waker.vtable.drop)(waker.waker);
+}
+
+// Host side
+impl<T> Future for FFIFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut cx = FFIContext {
+            waker: cx.waker() as *const Waker as *const c_void,
+            vtable: &HOST_WAKER_VTABLE,
+        };
+
+        match (self.poll)(self.future, &mut cx) {
+            FFIPoll::Ready(value) => Poll::Ready(value),
+            FFIPoll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for FFIFuture<T> {
+    fn drop(&mut self) {
+        (self.drop)(self.future)
+    }
+}
+
+// The waker given by FFIContext is borrowed (&Waker), clones are boxed (Box<Waker>).
+// Sources only ever call wake/drop on the clones.
+static HOST_WAKER_VTABLE: FFIWakerVTable = FFIWakerVTable {
+    clone: clone_host_waker,
+    wake: wake_host_waker,
+    wake_by_ref: wake_by_ref_host_waker,
+    drop: drop_host_waker,
+};
+
+extern "C" fn clone_host_waker(waker: *const c_void) -> *const c_void {
+    let waker = unsafe { &*(waker as *const Waker) };
+    Box::into_raw(Box::new(waker.clone())) as *const c_void
+}
+
+extern "C" fn wake_host_waker(waker: *const c_void) {
+    let waker = unsafe { Box::from_raw(waker as *mut Waker) };
+    waker.wake();
+}
+
+extern "C" fn wake_by_ref_host_waker(waker: *const c_void) {
+    let waker = unsafe { &*(waker as *const Waker) };
+    waker.wake_by_ref();
+}
+
+extern "C" fn drop_host_waker(waker: *const c_void) {
+    drop(unsafe { Box::from_raw(waker as *mut Waker) });
+}
+
+struct ThreadWaker(std::thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Drives async source functions from their blocking ABI counterparts, see FFIFuture
+// for what those futures may not use
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::lifecycle::{ABIInitOutput, ABIStateHandle};
+    use crate::abi::primitives::ABIResultArray;
+
+    #[test]
+    fn futures_resolve_through_the_ffi_poll() {
+        let future = FFIFuture::<ABIResultArray>::new(async { Ok(vec!["page".to_owned()]).into() });
+        let result: Result<Vec<String>, SourceError> = block_on(future).into();
+        assert_eq!(result, Ok(vec!["page".to_owned()]));
+    }
+
+    #[test]
+    fn panics_while_polling_resolve_to_an_error() {
+        let future = FFIFuture::<ABIResultArray>::new(async {
+            panic!("there is no reactor running");
+        });
+        let result: Result<Vec<String>, SourceError> = block_on(future).into();
+        assert_eq!(
+            result,
+            Err(SourceError::Panicked(
+                "there is no reactor running".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn catch_panic_keeps_formatted_messages() {
+        let result: Result<Vec<String>, SourceError> =
+            catch_panic::<ABIResultArray>(|| panic!("status {}", 500)).into();
+        assert_eq!(result, Err(SourceError::Panicked("status 500".to_owned())));
+    }
+
+    #[test]
+    fn panicking_init_is_an_init_error() {
+        let result: Result<ABIStateHandle, SourceError> =
+            catch_panic::<ABIInitOutput>(|| panic!("no config")).into();
+        assert!(matches!(result, Err(SourceError::Panicked(message)) if message == "no config"));
+    }
+
+    #[test]
+    fn catch_panic_with_gets_the_message() {
+        let message = catch_panic_with(|| panic!("shutdown"), |message| message);
+        assert_eq!(message, "shutdown");
+        assert_eq!(catch_panic_with(|| 1, |_| 0), 1);
+    }
+}
@@ -0,0 +1,18 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::SourceError;
+
+use super::primitives::FFIString;
+
+// Structured values without a repr(C) counterpart (e.g.: preferences) cross the boundary as JSON
+pub fn encode<T: Serialize>(value: &T) -> FFIString {
+    match serde_json::to_string(value) {
+        Ok(encoded) => FFIString::from(encoded),
+        Err(_) => FFIString::null(),
+    }
+}
+
+pub fn decode<T: DeserializeOwned>(value: FFIString) -> Result<T, SourceError> {
+    let value: String = value.try_into()?;
+    serde_json::from_str(&value).map_err(|_| SourceError::Serialize)
+}
@@ -0,0 +1,95 @@
+use std::ffi::c_void;
+use std::sync::Arc;
+
+use crate::error::SourceError;
+
+use super::primitives::FFIString;
+use super::FromSourceError;
+
+// Both optional. `abi_init` is called once the library is loaded, before any other
+// function, with the JSON encoded stored Preferences, and `abi_shutdown` right before it
+// is unloaded, with the state `abi_init` returned. The state is passed to every other function
+pub type InitFn = extern "C" fn(FFIString) -> ABIInitOutput;
+pub type ShutdownFn = extern "C" fn(ABIStateHandle);
+
+// Opaque to the host. Null for sources without `abi_init`
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct ABIStateHandle(*const c_void);
+
+// Only built from Arc<T> with T: Send + Sync
+unsafe impl Send for ABIStateHandle {}
+unsafe impl Sync for ABIStateHandle {}
+
+impl ABIStateHandle {
+    pub fn null() -> Self {
+        Self(std::ptr::null())
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0.is_null()
+    }
+
+    pub fn from_arc<T: Send + Sync>(state: Arc<T>) -> Self {
+        Self(Arc::into_raw(state) as *const c_void)
+    }
+
+    /// # Safety
+    /// Must have been built by `from_arc::<T>` and not released by `into_arc` yet
+    pub unsafe fn clone_arc<T>(&self) -> Option<Arc<T>> {
+        if self.is_null() {
+            return None;
+        }
+
+        let state = self.0 as *const T;
+        Arc::increment_strong_count(state);
+        Some(Arc::from_raw(state))
+    }
+
+    /// # Safety
+    /// Must have been built by `from_arc::<T>`, the handle can't be used afterwards
+    pub unsafe fn into_arc<T>(self) -> Option<Arc<T>> {
+        match self.is_null() {
+            true => None,
+            false => Some(Arc::from_raw(self.0 as *const T)),
+        }
+    }
+}
+
+#[repr(C)]
+pub struct ABIInitOutput {
+    pub state: ABIStateHandle,
+    pub err: FFIString,
+}
+
+impl From<Result<ABIStateHandle, SourceError>> for ABIInitOutput {
+    fn from(result: Result<ABIStateHandle, SourceError>) -> Self {
+        match result {
+            Ok(state) => Self {
+                state,
+                err: FFIString::null(),
+            },
+            Err(err) => Self {
+                state: ABIStateHandle::null(),
+                err: FFIString::from(err.encode()),
+            },
+        }
+    }
+}
+
+impl FromSourceError for ABIInitOutput {
+    fn from_source_error(error: SourceError) -> Self {
+        Err(error).into()
+    }
+}
+
+impl From<ABIInitOutput> for Result<ABIStateHandle, SourceError> {
+    fn from(value: ABIInitOutput) -> Self {
+        if value.err.is_null() {
+            return Ok(value.state);
+        }
+
+        let err: String = value.err.try_into()?;
+        Err(SourceError::decode(&err))
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{error::SourceError, locale::Locale};
+
+// ASCII subtags, zero padded: language (ISO 639), script (ISO 15924) and
+// region (ISO 3166-1 alpha-2 or UN M.49)
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ABILocale {
+    pub language: [u8; 3],
+    pub script: [u8; 4],
+    pub region: [u8; 3],
+}
+
+fn copy_subtag<const N: usize>(subtag: Option<&str>) -> [u8; N] {
+    let mut bytes = [0; N];
+    let subtag = subtag.unwrap_or_default().as_bytes();
+    let len = subtag.len().min(N);
+    bytes[..len].copy_from_slice(&subtag[..len]);
+    bytes
+}
+
+fn subtag_str(subtag: &[u8]) -> Result<Option<&str>, SourceError> {
+    let len = subtag.iter().position(|b| *b == 0).unwrap_or(subtag.len());
+    let subtag = std::str::from_utf8(&subtag[..len])
+        .map_err(|_| SourceError::InvalidLocale(String::from_utf8_lossy(subtag).into_owned()))?;
+    Ok(Some(subtag).filter(|subtag| !subtag.is_empty()))
+}
+
+impl From<Locale> for ABILocale {
+    fn from(locale: Locale) -> Self {
+        Self {
+            language: copy_subtag(Some(locale.language())),
+            script: copy_subtag(locale.script()),
+            region: copy_subtag(locale.region()),
+        }
+    }
+}
+
+impl TryFrom<ABILocale> for Locale {
+    type Error = SourceError;
+
+    fn try_from(value: ABILocale) -> Result<Self, Self::Error> {
+        let language = subtag_str(&value.language)?.unwrap_or_default();
+        Locale::from_subtags(
+            language,
+            subtag_str(&value.script)?,
+            subtag_str(&value.region)?,
+        )
+    }
+}
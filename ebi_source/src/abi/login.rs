@@ -0,0 +1,39 @@
+use crate::error::SourceError;
+
+use super::{lifecycle::ABIStateHandle, primitives::FFIString, FromSourceError};
+
+// Optional. `abi_login` takes the JSON encoded Credentials, the session is kept by the source
+pub type LoginFn = extern "C" fn(ABIStateHandle, FFIString) -> ABILoginOutput;
+pub type LogoutFn = extern "C" fn(ABIStateHandle) -> ABILoginOutput;
+
+#[repr(C)]
+pub struct ABILoginOutput {
+    pub err: FFIString,
+}
+
+impl From<Result<(), SourceError>> for ABILoginOutput {
+    fn from(result: Result<(), SourceError>) -> Self {
+        let err = match result {
+            Ok(()) => FFIString::null(),
+            Err(err) => FFIString::from(err.encode()),
+        };
+        Self { err }
+    }
+}
+
+impl FromSourceError for ABILoginOutput {
+    fn from_source_error(error: SourceError) -> Self {
+        Err(error).into()
+    }
+}
+
+impl From<ABILoginOutput> for Result<(), SourceError> {
+    fn from(value: ABILoginOutput) -> Self {
+        if value.err.is_null() {
+            return Ok(());
+        }
+
+        let err: String = value.err.try_into()?;
+        Err(SourceError::decode(&err))
+    }
+}
@@ -68,7 +68,20 @@ impl TryInto<Manga> for ABIManga {
 }
 
 pub mod manga_list {
-    use crate::abi::primitives::ABIResultArray;
+    use crate::abi::{future::FFIFuture, lifecycle::ABIStateHandle, primitives::ABIResultArray};
 
-    pub type MangaListFn = extern "C" fn() -> ABIResultArray;
+    pub type MangaListFn = extern "C" fn(ABIStateHandle) -> ABIResultArray;
+    pub type MangaListAsyncFn = extern "C" fn(ABIStateHandle) -> FFIFuture<ABIResultArray>;
+}
+
+pub mod search {
+    use crate::abi::{
+        future::FFIFuture,
+        lifecycle::ABIStateHandle,
+        primitives::{ABIResultArray, FFIString},
+    };
+
+    // (state, query) => Vec<ABIManga>
+    pub type SearchFn = extern "C" fn(ABIStateHandle, FFIString) -> ABIResultArray;
+    pub type SearchAsyncFn = extern "C" fn(ABIStateHandle, FFIString) -> FFIFuture<ABIResultArray>;
 }
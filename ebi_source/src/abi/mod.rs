@@ -1,12 +1,39 @@
 pub mod chapter;
+pub mod cookies;
+pub mod future;
+pub mod json;
+pub mod lifecycle;
+pub mod locale;
+pub mod login;
 pub mod manga;
+pub mod preferences;
 pub mod source;
 
+// Bumped on every breaking change to the plugin ABI
+pub const ABI_VERSION: u32 = 7;
+
+// Every bump is breaking, so a source only works with the ABI version it was built
+// against. Checked on the library, its manifest and repository entries alike
+pub fn is_compatible(abi_version: u32) -> bool {
+    abi_version == ABI_VERSION
+}
+
+// Exported as `abi_version` alongside `abi_source_info`
+pub type AbiVersionFn = extern "C" fn() -> u32;
+
+use crate::error::SourceError;
+
+// ABI outputs carrying a SourceError, returned by the generated functions in place of a
+// panic or of arguments that could not be read
+pub trait FromSourceError {
+    fn from_source_error(error: SourceError) -> Self;
+}
+
 pub mod primitives {
     use std::ffi::{c_char, c_void};
     use std::mem::ManuallyDrop;
-    use std::str::FromStr;
 
+    use crate::abi::FromSourceError;
     use crate::error::SourceError;
 
     #[repr(C)]
@@ -116,18 +143,29 @@ pub mod primitives {
         pub err: FFIString,
     }
 
-    impl<T> Into<Result<Vec<T>, SourceError>> for ABIResultArray {
-        fn into(self) -> Result<Vec<T>, SourceError> {
-            if self.err.is_null() {
-                return self.result.try_into();
+    // Only built out of a `Vec<T: Send>` or an error string (see the From impl below),
+    // whose buffers it then owns alone: nothing else points to them once built, so it
+    // can be returned by futures polled on another thread
+    unsafe impl Send for ABIResultArray {}
+
+    impl FromSourceError for ABIResultArray {
+        fn from_source_error(error: SourceError) -> Self {
+            Err::<Vec<()>, _>(error).into()
+        }
+    }
+
+    impl<T> From<ABIResultArray> for Result<Vec<T>, SourceError> {
+        fn from(value: ABIResultArray) -> Self {
+            if value.err.is_null() {
+                return value.result.try_into();
             }
 
-            let err: String = self.err.try_into()?;
-            Err(SourceError::from_str(&err).unwrap()) // safe to unwrap
+            let err: String = value.err.try_into()?;
+            Err(SourceError::decode(&err))
         }
     }
 
-    impl<T> From<Result<Vec<T>, SourceError>> for ABIResultArray {
+    impl<T: Send> From<Result<Vec<T>, SourceError>> for ABIResultArray {
         fn from(value: Result<Vec<T>, SourceError>) -> Self {
             match value {
                 Ok(arr) => ABIResultArray {
@@ -136,7 +174,7 @@ pub mod primitives {
                 },
                 Err(e) => ABIResultArray {
                     result: FFIArray::null(),
-                    err: FFIString::from(e.to_string()),
+                    err: FFIString::from(e.encode()),
                 },
             }
         }
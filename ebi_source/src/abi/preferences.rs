@@ -0,0 +1,4 @@
+use super::primitives::FFIString;
+
+// Optional, exported by sources with preferences. Returns the JSON encoded PreferenceSchema
+pub type PreferencesSchemaFn = extern "C" fn() -> FFIString;
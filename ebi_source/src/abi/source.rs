@@ -1,15 +1,18 @@
-use std::str::FromStr;
-
 use crate::{error::SourceError, locale::Locale, Source};
 
-use super::primitives::FFIString;
+use super::{
+    locale::ABILocale,
+    primitives::{FFIArray, FFIString},
+};
 
 #[repr(C)]
 pub struct ABISource {
     pub identifier: FFIString,
     pub title: FFIString,
     pub description: FFIString,
-    pub locale: FFIString,
+    pub locale: ABILocale,
+    // Vec<ABILocale>
+    pub locales: FFIArray,
 }
 
 impl From<&Source> for ABISource {
@@ -17,13 +20,19 @@ impl From<&Source> for ABISource {
         let description = FFIString::from(source.description.clone());
         let identifier = FFIString::from(source.identifier.clone());
         let title = FFIString::from(source.title.clone());
-        let locale = FFIString::from(source.locale.to_string().clone());
+        let locale = ABILocale::from(source.locale);
+        let locales = source
+            .locales
+            .iter()
+            .map(|locale| ABILocale::from(*locale))
+            .collect::<Vec<ABILocale>>();
 
         Self {
             description,
             identifier,
             title,
             locale,
+            locales: FFIArray::from(locales),
         }
     }
 }
@@ -36,30 +45,37 @@ impl TryInto<Source> for ABISource {
         let identifier = self.identifier.try_into()?;
         let title = self.title.try_into()?;
 
-        let locale: String = self.locale.try_into()?;
-        let locale = Locale::from_str(&locale).unwrap(); // safe to unwrap
+        let locale = Locale::try_from(self.locale)?;
+        let locales: Vec<ABILocale> = self.locales.try_into()?;
+        let locales = locales
+            .into_iter()
+            .map(Locale::try_from)
+            .collect::<Result<Vec<Locale>, SourceError>>()?;
 
         Ok(Source {
             description,
             identifier,
             title,
             locale,
+            locales,
         })
     }
 }
 
 pub mod source_info {
-    use std::str::FromStr;
-
     use crate::{
-        abi::primitives::{FFIArray, FFIString},
+        abi::{
+            lifecycle::ABIStateHandle,
+            primitives::{FFIArray, FFIString},
+            FromSourceError,
+        },
         error::SourceError,
         Source,
     };
 
     use super::ABISource;
 
-    pub type SourceInfoFn = extern "C" fn() -> ABISourceInfoOutput;
+    pub type SourceInfoFn = extern "C" fn(ABIStateHandle) -> ABISourceInfoOutput;
 
     #[repr(C)]
     pub struct ABISourceInfoOutput {
@@ -79,20 +95,26 @@ pub mod source_info {
                 }
                 Err(err) => Self {
                     source: FFIArray::null(),
-                    err: FFIString::from(err.to_string()),
+                    err: FFIString::from(err.encode()),
                 },
             }
         }
     }
 
-    impl Into<Result<Source, SourceError>> for ABISourceInfoOutput {
-        fn into(self) -> Result<Source, SourceError> {
-            if !self.err.is_null() {
-                let err: String = self.err.try_into()?;
-                return Err(SourceError::from_str(&err).unwrap()); // safe to unwrap
+    impl FromSourceError for ABISourceInfoOutput {
+        fn from_source_error(error: SourceError) -> Self {
+            Err(error).into()
+        }
+    }
+
+    impl From<ABISourceInfoOutput> for Result<Source, SourceError> {
+        fn from(value: ABISourceInfoOutput) -> Self {
+            if !value.err.is_null() {
+                let err: String = value.err.try_into()?;
+                return Err(SourceError::decode(&err));
             }
 
-            let mut abi_sources: Vec<ABISource> = self.source.try_into()?;
+            let mut abi_sources: Vec<ABISource> = value.source.try_into()?;
 
             match abi_sources.len() {
                 0 => Err(SourceError::InvalidSource),
@@ -0,0 +1,375 @@
+use std::ffi::c_void;
+
+use serde::{Deserialize, Serialize};
+
+use crate::abi::cookies::ABICookieJar;
+use crate::abi::primitives::FFIString;
+use crate::util::now;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    // Set without a Domain attribute, only sent back to that exact host
+    pub host_only: bool,
+    pub path: String,
+    // Unix timestamp (seconds), None for session cookies
+    pub expires: Option<u64>,
+    pub secure: bool,
+    pub http_only: bool,
+}
+
+// (scheme, host, path)
+fn url_parts(url: &str) -> Option<(&str, String, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let (authority, path) = match rest.find(['/', '?', '#']) {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?.to_ascii_lowercase();
+    let path = path.split(['?', '#']).next().unwrap_or("/");
+    let path = if path.is_empty() { "/" } else { path };
+
+    Some((scheme, host, path))
+}
+
+// Lowercased host of `url`
+pub fn url_host(url: &str) -> Option<String> {
+    url_parts(url).map(|(_, host, _)| host)
+}
+
+// Directory of the request path, used when no Path attribute is set
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(idx) => path[..idx].to_owned(),
+    }
+}
+
+impl Cookie {
+    // Parses a Set-Cookie header received from `url`
+    pub fn parse(set_cookie: &str, url: &str) -> Option<Self> {
+        let (_, host, request_path) = url_parts(url)?;
+        let mut attributes = set_cookie.split(';');
+
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Self {
+            name: name.to_owned(),
+            value: value.trim().trim_matches('"').to_owned(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(request_path),
+            expires: None,
+            secure: false,
+            http_only: false,
+        };
+
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (attribute.trim(), ""),
+            };
+
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    // A site can't set cookies for another one. Public suffixes (e.g.:
+                    // Domain=com) are rejected by the host store
+                    if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_owned(),
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => cookie.expires = cookie.expires.or_else(|| parse_http_date(value)),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+
+        // Max-Age takes precedence over Expires
+        if let Some(max_age) = max_age {
+            cookie.expires = Some(match max_age {
+                max_age if max_age <= 0 => 0,
+                max_age => now().saturating_add(max_age as u64),
+            });
+        }
+
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    // Whether the cookie must be sent with a request to `url`
+    pub fn matches(&self, url: &str) -> bool {
+        let (scheme, host, path) = match url_parts(url) {
+            Some(parts) => parts,
+            None => return false,
+        };
+
+        let domain_matches = match self.host_only {
+            true => host == self.domain,
+            false => host == self.domain || host.ends_with(&format!(".{}", self.domain)),
+        };
+        let path_matches = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+
+        domain_matches && path_matches && (!self.secure || scheme == "https")
+    }
+
+    // Cookies are replaced by the ones with the same name, domain and path
+    pub fn same_key(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+// Value of the Cookie header sent to `url`
+pub fn cookie_header(cookies: &[Cookie], url: &str) -> Option<String> {
+    let now = now();
+    let header = cookies
+        .iter()
+        .filter(|cookie| !cookie.is_expired(now) && cookie.matches(url))
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect::<Vec<String>>()
+        .join("; ");
+
+    match header.is_empty() {
+        true => None,
+        false => Some(header),
+    }
+}
+
+// e.g.: "Wed, 21 Oct 2015 07:28:00 GMT" (or with dashes: "21-Oct-2015")
+fn parse_http_date(date: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let date = date.split_once(',').map_or(date, |(_, date)| date);
+    let date = date.replace('-', " ");
+    let mut parts = date.split_whitespace();
+
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|m| month.starts_with(m))? as u64 + 1;
+    let year: u64 = match parts.next()?.parse().ok()? {
+        year if year < 70 => year + 2000,
+        year if year < 100 => year + 1900,
+        year => year,
+    };
+
+    let mut time = parts.next()?.split(':').map(|t| t.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    // Out of range dates are invalid, as in RFC 6265 5.1.1
+    if !(1..=31).contains(&day)
+        || !(1601..=9999).contains(&year)
+        || hours >= 24
+        || minutes >= 60
+        || seconds >= 60
+    {
+        return None;
+    }
+
+    // Days since 1970-01-01 (http://howardhinnant.github.io/date_algorithms.html)
+    let (year, month) = match month <= 2 {
+        true => (year - 1, month + 9),
+        false => (year, month - 3),
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+
+    days.checked_mul(86400)?
+        .checked_add(hours * 3600 + minutes * 60 + seconds)
+}
+
+// Host cookie store of the source, given to `set_cookie_jar`. Requests made by the
+// source should send `cookie_header` and report the Set-Cookie headers they receive,
+// so the host (e.g.: when downloading pages) shares the same session
+pub struct CookieJar {
+    jar: ABICookieJar,
+}
+
+// The host store is thread safe and outlives the source state
+unsafe impl Send for CookieJar {}
+unsafe impl Sync for CookieJar {}
+
+impl From<ABICookieJar> for CookieJar {
+    fn from(jar: ABICookieJar) -> Self {
+        Self { jar }
+    }
+}
+
+impl CookieJar {
+    fn store(&self) -> *const c_void {
+        self.jar.store
+    }
+
+    pub fn cookie_header(&self, url: &str) -> Option<String> {
+        let header = (self.jar.cookie_header)(self.store(), FFIString::from(url.to_owned()));
+        match header.is_null() {
+            true => None,
+            false => header.try_into().ok(),
+        }
+    }
+
+    pub fn set_cookie(&self, url: &str, set_cookie: &str) {
+        (self.jar.set_cookie)(
+            self.store(),
+            FFIString::from(url.to_owned()),
+            FFIString::from(set_cookie.to_owned()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://www.example.com/manga/1";
+
+    #[test]
+    fn parses_attributes() {
+        let cookie = Cookie::parse(
+            "session=\"abc\"; Domain=.Example.com; Path=/; Secure; HttpOnly",
+            URL,
+        )
+        .unwrap();
+
+        assert_eq!(cookie.name, "session");
+        assert_eq!(cookie.value, "abc");
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.host_only);
+        assert_eq!(cookie.path, "/");
+        assert_eq!(cookie.expires, None);
+        assert!(cookie.secure && cookie.http_only);
+        assert!(cookie.matches("https://cdn.example.com/page.png"));
+        assert!(!cookie.matches("http://www.example.com/"));
+    }
+
+    #[test]
+    fn defaults_to_host_only_and_request_directory() {
+        let cookie = Cookie::parse("a=1", URL).unwrap();
+
+        assert_eq!(cookie.domain, "www.example.com");
+        assert!(cookie.host_only);
+        assert_eq!(cookie.path, "/manga");
+        assert!(cookie.matches("https://www.example.com/manga/2"));
+        assert!(!cookie.matches("https://www.example.com/mangas"));
+        assert!(!cookie.matches("https://cdn.example.com/manga/2"));
+    }
+
+    #[test]
+    fn rejects_invalid_cookies() {
+        assert_eq!(Cookie::parse("no_value", URL), None);
+        assert_eq!(Cookie::parse("=1", URL), None);
+        assert_eq!(Cookie::parse("a=1", "not a url"), None);
+        assert_eq!(Cookie::parse("a=1; Domain=other.com", URL), None);
+        assert_eq!(Cookie::parse("a=1; Domain=ample.com", URL), None);
+    }
+
+    #[test]
+    fn max_age_takes_precedence_over_expires() {
+        let cookie = Cookie::parse(
+            "a=1; Max-Age=60; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+            URL,
+        )
+        .unwrap();
+        assert!(cookie.expires.unwrap() >= now() + 59);
+
+        let cookie = Cookie::parse("a=1; Max-Age=0", URL).unwrap();
+        assert!(cookie.is_expired(now()));
+
+        let cookie = Cookie::parse("a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT", URL).unwrap();
+        assert_eq!(cookie.expires, Some(1445412480));
+        assert!(cookie.is_expired(now()));
+    }
+
+    #[test]
+    fn parses_http_dates() {
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(1445412480)
+        );
+        assert_eq!(
+            parse_http_date("Wednesday, 21-Oct-15 07:28:00 GMT"),
+            Some(1445412480)
+        );
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2028 23:59:59 GMT"),
+            Some(1835481599)
+        );
+        assert_eq!(parse_http_date("Thu, 31 Dec 1969 23:59:59 GMT"), None);
+        assert_eq!(parse_http_date("Wed, 21 Foo 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_http_date("Wed, 21 Oct 2015"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+
+    #[test]
+    fn rejects_out_of_range_dates() {
+        for date in [
+            "Thu, 01 Jan 2026 99999999999999999:00:00 GMT",
+            "Thu, 01 Jan 2026 00:99999999999999999:00 GMT",
+            "Thu, 01 Jan 99999999999999999 00:00:00 GMT",
+            "Thu, 99999999999999999 Jan 2026 00:00:00 GMT",
+            "Thu, 00 Jan 2026 00:00:00 GMT",
+            "Thu, 32 Jan 2026 00:00:00 GMT",
+            "Thu, 01 Jan 2026 24:00:00 GMT",
+            "Thu, 01 Jan 2026 00:60:00 GMT",
+            "Thu, 01 Jan 2026 00:00:60 GMT",
+            "Thu, 01 Jan 1600 00:00:00 GMT",
+            "Thu, 01 Jan 10000 00:00:00 GMT",
+        ] {
+            assert_eq!(parse_http_date(date), None, "{}", date);
+        }
+        assert_eq!(
+            parse_http_date("Fri, 31 Dec 9999 23:59:59 GMT"),
+            Some(253402300799)
+        );
+
+        let cookie = Cookie::parse(
+            "a=b; Expires=Thu, 01 Jan 2026 99999999999999999:00:00 GMT",
+            URL,
+        )
+        .unwrap();
+        assert_eq!(cookie.expires, None);
+        let cookie = Cookie::parse(&format!("a=b; Max-Age={}", i64::MAX), URL).unwrap();
+        assert!(cookie.expires.is_some());
+    }
+
+    #[test]
+    fn builds_cookie_header() {
+        let cookies = [
+            Cookie::parse("a=1", URL).unwrap(),
+            Cookie::parse("b=2; Path=/", URL).unwrap(),
+            Cookie::parse("c=3; Max-Age=0", URL).unwrap(),
+        ];
+
+        assert_eq!(
+            cookie_header(&cookies, "https://www.example.com/manga/1/2"),
+            Some("a=1; b=2".to_owned())
+        );
+        assert_eq!(
+            cookie_header(&cookies, "https://www.example.com/"),
+            Some("b=2".to_owned())
+        );
+        assert_eq!(cookie_header(&cookies, "https://example.org/"), None);
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+// Given to a source's `login`, stored encrypted by the host to log back in when needed
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Credentials {
+    Password { username: String, password: String },
+    Token { token: String },
+}
+
+// Secrets are never printed
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Password { username, .. } => f
+                .debug_struct("Password")
+                .field("username", username)
+                .field("password", &"***")
+                .finish(),
+            Self::Token { .. } => f.debug_struct("Token").field("token", &"***").finish(),
+        }
+    }
+}
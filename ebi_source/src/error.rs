@@ -3,7 +3,7 @@ use std::ffi::NulError;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Error, Debug, Serialize, Deserialize)]
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SourceError {
     #[error("UNKNOWN_ERROR::{0}")]
     Unknown(String),
@@ -16,6 +16,29 @@ pub enum SourceError {
     #[error("INVALID_SOURCE_PROVIDED")]
     InvalidSource,
 
+    #[error("NOT_FOUND")]
+    NotFound,
+    // retry_after in seconds
+    #[error("RATE_LIMITED")]
+    RateLimited { retry_after: Option<u64> },
+    #[error("LOGIN_REQUIRED")]
+    LoginRequired,
+    #[error("CLOUDFLARE_PROTECTION")]
+    Cloudflare,
+    #[error("HTTP_ERROR::{status}::{message}")]
+    Http { status: u16, message: String },
+    // Called without the state created by abi_init
+    #[error("SOURCE_NOT_INITIALIZED")]
+    Uninitialized,
+    #[error("INVALID_LOCALE::{0}")]
+    InvalidLocale(String),
+    // The schema declared by the source, with the offending preference
+    #[error("INVALID_PREFERENCE_SCHEMA::{0}")]
+    InvalidPreferenceSchema(String),
+    // Caught before unwinding out of the source, with the panic message
+    #[error("SOURCE_PANICKED::{0}")]
+    Panicked(String),
+
     #[error("ABI_NULL_CONVERSION_ERROR")]
     ABINullConversion,
     #[error("ABI_RESULT_ERROR::{0}")]
@@ -27,6 +50,40 @@ pub struct SourceErrorSerialized {
     pub error: SourceError,
 }
 
+impl SourceError {
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            Self::Http { status, .. } => Some(*status),
+            Self::NotFound => Some(404),
+            Self::RateLimited { .. } => Some(429),
+            _ => None,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    // Representation sent through the ABI boundary
+    pub fn encode(&self) -> String {
+        let serialized = SourceErrorSerialized {
+            error: self.clone(),
+        };
+        serde_json::to_string(&serialized).unwrap_or_else(|_| self.to_string())
+    }
+
+    // Accepts both encoded errors and the plain strings sent by older sources
+    pub fn decode(encoded: &str) -> Self {
+        match serde_json::from_str::<SourceErrorSerialized>(encoded) {
+            Ok(serialized) => serialized.error,
+            Err(_) => encoded.parse().unwrap(), // safe to unwrap
+        }
+    }
+}
+
 impl std::convert::From<NulError> for SourceError {
     fn from(_: NulError) -> Self {
         Self::ABINullConversion
@@ -37,12 +94,104 @@ impl std::str::FromStr for SourceError {
     type Err = SourceError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(msg) = s.strip_prefix("UNKNOWN_ERROR::") {
+            return Ok(Self::Unknown(msg.to_string()));
+        }
+        if let Some(locale) = s.strip_prefix("INVALID_LOCALE::") {
+            return Ok(Self::InvalidLocale(locale.to_string()));
+        }
+        if let Some(msg) = s.strip_prefix("INVALID_PREFERENCE_SCHEMA::") {
+            return Ok(Self::InvalidPreferenceSchema(msg.to_string()));
+        }
+        if let Some(msg) = s.strip_prefix("SOURCE_PANICKED::") {
+            return Ok(Self::Panicked(msg.to_string()));
+        }
+        if let Some(msg) = s.strip_prefix("ABI_RESULT_ERROR::") {
+            return Ok(Self::ABIResult(msg.to_string()));
+        }
+        let http = s
+            .strip_prefix("HTTP_ERROR::")
+            .and_then(|http| http.split_once("::"))
+            .and_then(|(status, message)| Some((status.parse().ok()?, message)));
+        if let Some((status, message)) = http {
+            return Ok(Self::Http {
+                status,
+                message: message.to_string(),
+            });
+        }
+
         match s {
             "COULD_NOT_FETCH_DATA" => Ok(Self::Fetch),
             "COULD_NOT_SERIALIZE_DATA" => Ok(Self::Serialize),
             "INVALID_IDENTIFIER_PROVIDED" => Ok(Self::InvalidIdentifier),
             "INVALID_SOURCE_PROVIDED" => Ok(Self::InvalidSource),
+            "NOT_FOUND" => Ok(Self::NotFound),
+            // retry_after is only kept by encode/decode
+            "RATE_LIMITED" => Ok(Self::RateLimited { retry_after: None }),
+            "LOGIN_REQUIRED" => Ok(Self::LoginRequired),
+            "SOURCE_NOT_INITIALIZED" => Ok(Self::Uninitialized),
+            "CLOUDFLARE_PROTECTION" => Ok(Self::Cloudflare),
+            "ABI_NULL_CONVERSION_ERROR" => Ok(Self::ABINullConversion),
             _ => Ok(Self::Unknown(s.to_string())),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors() -> Vec<SourceError> {
+        vec![
+            SourceError::Unknown("unexpected::response".to_owned()),
+            SourceError::Fetch,
+            SourceError::Serialize,
+            SourceError::InvalidIdentifier,
+            SourceError::InvalidSource,
+            SourceError::NotFound,
+            SourceError::RateLimited { retry_after: None },
+            SourceError::LoginRequired,
+            SourceError::Cloudflare,
+            SourceError::Http {
+                status: 503,
+                message: "Service::Unavailable".to_owned(),
+            },
+            SourceError::Uninitialized,
+            SourceError::InvalidLocale("xx-YY".to_owned()),
+            SourceError::InvalidPreferenceSchema("quality :: no options".to_owned()),
+            SourceError::Panicked("there is no reactor running".to_owned()),
+            SourceError::ABINullConversion,
+            SourceError::ABIResult("null pointer".to_owned()),
+        ]
+    }
+
+    #[test]
+    fn display_round_trips_through_from_str() {
+        for error in errors() {
+            assert_eq!(error.to_string().parse::<SourceError>(), Ok(error));
+        }
+    }
+
+    #[test]
+    fn encode_round_trips_through_decode() {
+        let mut errors = errors();
+        errors.push(SourceError::RateLimited {
+            retry_after: Some(30),
+        });
+        for error in errors {
+            assert_eq!(SourceError::decode(&error.encode()), error);
+        }
+    }
+
+    #[test]
+    fn unknown_strings_are_kept() {
+        assert_eq!(
+            SourceError::decode("SOMETHING_ELSE"),
+            SourceError::Unknown("SOMETHING_ELSE".to_owned())
+        );
+        assert_eq!(
+            "HTTP_ERROR::teapot::".parse::<SourceError>(),
+            Ok(SourceError::Unknown("HTTP_ERROR::teapot::".to_owned()))
+        );
+    }
+}
@@ -1,16 +1,25 @@
 use serde::{Deserialize, Serialize};
 
 pub mod abi;
+pub mod cookies;
+pub mod credentials;
 pub mod error;
 pub mod locale;
+pub mod manifest;
+pub mod preferences;
+pub mod util;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Chapter {
-    pub chapter: u32,
+    // Fractional for extras and splits, e.g.: 10.5
+    pub chapter: f32,
     pub title: String,
     pub url: String,
     pub manga: String,
     pub source: String,
+    // Multi-language sources list the same chapter once per language
+    #[serde(default)]
+    pub language: locale::Locale,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -30,6 +39,20 @@ pub struct Source {
     pub title: String,
     pub description: String,
     pub locale: locale::Locale,
+    // Languages chapters are available in, for sources serving several of them
+    // (`locale` is then Locale::ALL). Empty when only `locale`
+    #[serde(default)]
+    pub locales: Vec<locale::Locale>,
+}
+
+impl Source {
+    // See Locale::matches
+    pub fn supports_locale(&self, locale: &locale::Locale) -> bool {
+        match self.locales.is_empty() {
+            true => self.locale.matches(locale),
+            false => self.locales.iter().any(|l| l.matches(locale)),
+        }
+    }
 }
 
 pub trait SourceLoader {
@@ -42,6 +65,11 @@ pub trait SourceLoader {
     fn manga_list(&self) -> Result<Vec<Manga>, Self::Error>;
 
     // Chapter functions
-    fn chapter_list(&self, manga: &Manga) -> Result<Vec<Chapter>, Self::Error>;
+    // `languages` are the ones preferred by the user, every language when empty
+    fn chapter_list(
+        &self,
+        manga: &Manga,
+        languages: &[locale::Locale],
+    ) -> Result<Vec<Chapter>, Self::Error>;
     fn chapter_page_list(&self, chapter: &Chapter) -> Result<Vec<String>, Self::Error>;
 }
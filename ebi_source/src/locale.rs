@@ -2,46 +2,229 @@ use std::str::FromStr;
 
 use serde::{de::Visitor, Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Locale {
-    Unknown,
-    EnUs,
-    PtBr,
+use crate::error::SourceError;
+
+// BCP-47 language tag, limited to its language, script and region subtags
+// (variants and extensions are dropped), e.g.: "en-US", "es-419", "zh-Hant-TW".
+// Subtags are ASCII, zero padded, and normalized to their canonical case
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Locale {
+    // ISO 639, lowercase
+    language: [u8; 3],
+    // ISO 15924, titlecase
+    script: [u8; 4],
+    // ISO 3166-1 alpha-2 uppercase, or UN M.49 digits
+    region: [u8; 3],
+}
+
+const fn subtag<const N: usize>(value: &str) -> [u8; N] {
+    let bytes = value.as_bytes();
+    let mut subtag = [0; N];
+    let mut i = 0;
+    while i < bytes.len() && i < N {
+        subtag[i] = bytes[i];
+        i += 1;
+    }
+    subtag
+}
+
+const fn locale(language: &str, script: &str, region: &str) -> Locale {
+    Locale {
+        language: subtag(language),
+        script: subtag(script),
+        region: subtag(region),
+    }
+}
+
+fn subtag_str(subtag: &[u8]) -> &str {
+    let len = subtag.iter().position(|b| *b == 0).unwrap_or(subtag.len());
+    std::str::from_utf8(&subtag[..len]).unwrap_or_default()
+}
+
+impl Locale {
+    // Aggregator sources with content in several languages ("mul")
+    pub const ALL: Self = locale("mul", "", "");
+    pub const UNKNOWN: Self = locale("und", "", "");
+
+    pub const AR: Self = locale("ar", "", "");
+    pub const DE: Self = locale("de", "", "");
+    pub const EN: Self = locale("en", "", "");
+    pub const EN_GB: Self = locale("en", "", "GB");
+    pub const EN_US: Self = locale("en", "", "US");
+    pub const ES: Self = locale("es", "", "");
+    pub const ES_419: Self = locale("es", "", "419");
+    pub const FR: Self = locale("fr", "", "");
+    pub const ID: Self = locale("id", "", "");
+    pub const IT: Self = locale("it", "", "");
+    pub const JA: Self = locale("ja", "", "");
+    pub const KO: Self = locale("ko", "", "");
+    pub const PT: Self = locale("pt", "", "");
+    pub const PT_BR: Self = locale("pt", "", "BR");
+    pub const RU: Self = locale("ru", "", "");
+    pub const TH: Self = locale("th", "", "");
+    pub const TR: Self = locale("tr", "", "");
+    pub const VI: Self = locale("vi", "", "");
+    pub const ZH: Self = locale("zh", "", "");
+    pub const ZH_HANS: Self = locale("zh", "Hans", "");
+    pub const ZH_HANT: Self = locale("zh", "Hant", "");
+
+    // Validates and normalizes each subtag, e.g.: ("EN", None, Some("us")) is en-US
+    pub fn from_subtags(
+        language: &str,
+        script: Option<&str>,
+        region: Option<&str>,
+    ) -> Result<Self, SourceError> {
+        let invalid = || {
+            let tag = [Some(language), script, region]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<&str>>()
+                .join("-");
+            SourceError::InvalidLocale(tag)
+        };
+
+        let is_alpha = |subtag: &str| subtag.bytes().all(|b| b.is_ascii_alphabetic());
+        if !(2..=3).contains(&language.len()) || !is_alpha(language) {
+            return Err(invalid());
+        }
+
+        let script = match script {
+            Some(script) if script.len() == 4 && is_alpha(script) => {
+                let (first, rest) = script.split_at(1);
+                format!(
+                    "{}{}",
+                    first.to_ascii_uppercase(),
+                    rest.to_ascii_lowercase()
+                )
+            }
+            Some(_) => return Err(invalid()),
+            None => String::new(),
+        };
+
+        let region = match region {
+            Some(region) if region.len() == 2 && is_alpha(region) => region.to_ascii_uppercase(),
+            Some(region) if region.len() == 3 && region.bytes().all(|b| b.is_ascii_digit()) => {
+                region.to_owned()
+            }
+            Some(_) => return Err(invalid()),
+            None => String::new(),
+        };
+
+        Ok(locale(&language.to_ascii_lowercase(), &script, &region))
+    }
+
+    pub fn language(&self) -> &str {
+        subtag_str(&self.language)
+    }
+
+    pub fn script(&self) -> Option<&str> {
+        Some(subtag_str(&self.script)).filter(|script| !script.is_empty())
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        Some(subtag_str(&self.region)).filter(|region| !region.is_empty())
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == Self::ALL
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        *self == Self::UNKNOWN
+    }
+
+    // Whether content in this locale is wanted by someone asking for `filter`.
+    // Subtags missing from the filter match any value, so "es" matches "es-419",
+    // and sources in every language match any filter
+    pub fn matches(&self, filter: &Locale) -> bool {
+        if self.is_all() || filter.is_all() {
+            return true;
+        }
+
+        self.language == filter.language
+            && (filter.script().is_none() || self.script == filter.script)
+            && (filter.region().is_none() || self.region == filter.region)
+    }
+
+    // English name, e.g.: "Chinese (Traditional, Taiwan)". Codes without a known
+    // name are shown as is
+    pub fn display_name(&self) -> String {
+        let language = lookup(LANGUAGE_NAMES, self.language());
+        let details = [
+            self.script().map(|script| lookup(SCRIPT_NAMES, script)),
+            self.region().map(|region| lookup(REGION_NAMES, region)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<&str>>();
+
+        match details.is_empty() {
+            true => language.to_owned(),
+            false => format!("{} ({})", language, details.join(", ")),
+        }
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::UNKNOWN
+    }
 }
 
+// Codes used by older sources and manifests
 impl std::convert::From<u8> for Locale {
     fn from(value: u8) -> Self {
         match value {
-            1 => Self::EnUs,
-            2 => Self::PtBr,
-            _ => Self::Unknown,
+            1 => Self::EN_US,
+            2 => Self::PT_BR,
+            _ => Self::UNKNOWN,
         }
     }
 }
 
+// Accepts "_" as separator and the "all"/"unknown" names, as used by older sources
 impl std::str::FromStr for Locale {
-    type Err = ();
+    type Err = SourceError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        Ok(match value {
-            "en_US" => Self::EnUs,
-            "pt_BR" => Self::PtBr,
-            _ => Self::Unknown,
-        })
+        match value.to_ascii_lowercase().as_str() {
+            "all" => return Ok(Self::ALL),
+            "unknown" => return Ok(Self::UNKNOWN),
+            _ => {}
+        }
+
+        let mut subtags = value.split(['-', '_']).peekable();
+        let language = subtags.next().unwrap_or_default();
+        let script = subtags.next_if(|subtag| subtag.len() == 4);
+        let region = subtags.next_if(|subtag| (2..=3).contains(&subtag.len()));
+
+        // Dropped, but must still be variants (e.g.: "posix", "1996") or start
+        // an extension (e.g.: "u-co-phonebk"), so typos such as "en-U" are invalid
+        let invalid = || SourceError::InvalidLocale(value.to_owned());
+        if let Some(subtag) = subtags.next() {
+            let is_alphanumeric = subtag.bytes().all(|b| b.is_ascii_alphanumeric());
+            let is_variant = (5..=8).contains(&subtag.len())
+                || (subtag.len() == 4 && subtag.as_bytes()[0].is_ascii_digit());
+            let is_extension = subtag.len() == 1 && subtags.peek().is_some_and(|s| !s.is_empty());
+            if !is_alphanumeric || !(is_variant || is_extension) {
+                return Err(invalid());
+            }
+        }
+
+        Self::from_subtags(language, script, region).map_err(|_| invalid())
     }
 }
 
 impl std::fmt::Display for Locale {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Unknown => "unknown",
-                Self::EnUs => "en_US",
-                Self::PtBr => "pt_BR",
-            }
-        )
+        write!(f, "{}", self.language())?;
+        if let Some(script) = self.script() {
+            write!(f, "-{}", script)?;
+        }
+        if let Some(region) = self.region() {
+            write!(f, "-{}", region)?;
+        }
+        Ok(())
     }
 }
 
@@ -51,7 +234,7 @@ impl<'de> Visitor<'de> for LocaleVisitor {
     type Value = Locale;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("a valid locale string, e.g., \"en_US\", \"pt_BR\"")
+        formatter.write_str("a BCP-47 language tag, e.g., \"en-US\", \"pt-BR\", \"ja\"")
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -74,6 +257,13 @@ impl<'de> Visitor<'de> for LocaleVisitor {
     {
         Ok(Locale::from(v))
     }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(u8::try_from(v).map(Locale::from).unwrap_or_default())
+    }
 }
 
 impl Serialize for Locale {
@@ -93,3 +283,215 @@ impl<'de> Deserialize<'de> for Locale {
         deserializer.deserialize_any(LocaleVisitor)
     }
 }
+
+fn lookup<'a>(names: &[(&str, &'static str)], code: &'a str) -> &'a str {
+    names
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map_or(code, |(_, name)| name)
+}
+
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("mul", "Multiple languages"),
+    ("und", "Unknown"),
+    ("ar", "Arabic"),
+    ("bg", "Bulgarian"),
+    ("bn", "Bengali"),
+    ("ca", "Catalan"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fi", "Finnish"),
+    ("fil", "Filipino"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hr", "Croatian"),
+    ("hu", "Hungarian"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("kk", "Kazakh"),
+    ("ko", "Korean"),
+    ("la", "Latin"),
+    ("lt", "Lithuanian"),
+    ("mn", "Mongolian"),
+    ("ms", "Malay"),
+    ("my", "Burmese"),
+    ("ne", "Nepali"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sk", "Slovak"),
+    ("sr", "Serbian"),
+    ("sv", "Swedish"),
+    ("ta", "Tamil"),
+    ("th", "Thai"),
+    ("tl", "Tagalog"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("ur", "Urdu"),
+    ("vi", "Vietnamese"),
+    ("zh", "Chinese"),
+];
+
+const SCRIPT_NAMES: &[(&str, &str)] = &[
+    ("Arab", "Arabic"),
+    ("Cyrl", "Cyrillic"),
+    ("Hans", "Simplified"),
+    ("Hant", "Traditional"),
+    ("Jpan", "Japanese"),
+    ("Kore", "Korean"),
+    ("Latn", "Latin"),
+];
+
+const REGION_NAMES: &[(&str, &str)] = &[
+    ("419", "Latin America"),
+    ("AR", "Argentina"),
+    ("AU", "Australia"),
+    ("BR", "Brazil"),
+    ("CA", "Canada"),
+    ("CN", "China"),
+    ("DE", "Germany"),
+    ("ES", "Spain"),
+    ("FR", "France"),
+    ("GB", "United Kingdom"),
+    ("HK", "Hong Kong"),
+    ("ID", "Indonesia"),
+    ("IN", "India"),
+    ("IT", "Italy"),
+    ("JP", "Japan"),
+    ("KR", "South Korea"),
+    ("MX", "Mexico"),
+    ("MY", "Malaysia"),
+    ("PH", "Philippines"),
+    ("PT", "Portugal"),
+    ("RU", "Russia"),
+    ("SA", "Saudi Arabia"),
+    ("TH", "Thailand"),
+    ("TR", "Turkey"),
+    ("TW", "Taiwan"),
+    ("UA", "Ukraine"),
+    ("US", "United States"),
+    ("VN", "Vietnam"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::locale::ABILocale;
+
+    fn locale(tag: &str) -> Locale {
+        Locale::from_str(tag).unwrap()
+    }
+
+    #[test]
+    fn parses_and_normalizes_tags() {
+        assert_eq!(locale("en"), Locale::EN);
+        assert_eq!(locale("en-US"), Locale::EN_US);
+        assert_eq!(locale("EN_us"), Locale::EN_US);
+        assert_eq!(locale("es-419"), Locale::ES_419);
+        assert_eq!(locale("zh-hant"), Locale::ZH_HANT);
+        assert_eq!(
+            locale("fil"),
+            Locale::from_subtags("fil", None, None).unwrap()
+        );
+
+        let locale = locale("zh-hANT-tw");
+        assert_eq!(locale.language(), "zh");
+        assert_eq!(locale.script(), Some("Hant"));
+        assert_eq!(locale.region(), Some("TW"));
+        assert_eq!(locale.to_string(), "zh-Hant-TW");
+    }
+
+    #[test]
+    fn accepts_legacy_names_and_drops_variants() {
+        assert_eq!(locale("all"), Locale::ALL);
+        assert_eq!(locale("Unknown"), Locale::UNKNOWN);
+        assert_eq!(locale("mul"), Locale::ALL);
+        assert_eq!(locale("en-US-posix"), Locale::EN_US);
+        assert_eq!(locale("de-DE-u-co-phonebk").to_string(), "de-DE");
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        for tag in [
+            "",
+            "e",
+            "engl",
+            "e1",
+            "en-",
+            "en-U",
+            "en-1234",
+            "en-Latn-USA",
+            "pt-B1",
+        ] {
+            assert!(
+                matches!(Locale::from_str(tag), Err(SourceError::InvalidLocale(_))),
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn matches_filters() {
+        assert!(Locale::ES_419.matches(&Locale::ES));
+        assert!(Locale::ES.matches(&Locale::ES));
+        assert!(!Locale::ES.matches(&Locale::ES_419));
+        assert!(!Locale::PT_BR.matches(&Locale::ES));
+        assert!(locale("zh-Hant-TW").matches(&Locale::ZH_HANT));
+        assert!(!Locale::ZH_HANS.matches(&Locale::ZH_HANT));
+
+        assert!(Locale::ALL.matches(&Locale::JA));
+        assert!(Locale::JA.matches(&Locale::ALL));
+        assert!(!Locale::UNKNOWN.matches(&Locale::JA));
+    }
+
+    #[test]
+    fn round_trips_through_the_abi() {
+        for locale in [
+            Locale::ALL,
+            Locale::UNKNOWN,
+            Locale::EN_US,
+            Locale::ES_419,
+            Locale::ZH_HANT,
+            locale("fil-PH"),
+            locale("zh-Hant-TW"),
+        ] {
+            assert_eq!(Locale::try_from(ABILocale::from(locale)).unwrap(), locale);
+        }
+
+        let invalid = ABILocale {
+            language: *b"e1\0",
+            script: [0; 4],
+            region: [0; 3],
+        };
+        assert!(Locale::try_from(invalid).is_err());
+
+        let invalid = ABILocale {
+            language: [0xff, b'n', 0],
+            script: [0; 4],
+            region: [0; 3],
+        };
+        assert!(Locale::try_from(invalid).is_err());
+    }
+
+    #[test]
+    fn deserializes_tags_and_legacy_codes() {
+        let locales: Vec<Locale> = serde_json::from_str(r#"["pt-BR", 1, 9]"#).unwrap();
+        assert_eq!(locales, [Locale::PT_BR, Locale::EN_US, Locale::UNKNOWN]);
+        assert_eq!(
+            serde_json::to_string(&Locale::ES_419).unwrap(),
+            r#""es-419""#
+        );
+        assert!(serde_json::from_str::<Locale>(r#""e""#).is_err());
+    }
+}
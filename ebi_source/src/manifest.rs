@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::locale::Locale;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+// Shipped as <source_dir>/manifest.json, so hosts can inspect a source without loading it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SourceManifest {
+    pub identifier: String,
+    pub title: String,
+    pub version: String,
+    pub abi_version: u32,
+    #[serde(default)]
+    pub locales: Vec<Locale>,
+    #[serde(default)]
+    pub nsfw: bool,
+    // Relative to the source directory
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub base_urls: Vec<String>,
+    #[serde(default)]
+    pub capabilities: SourceCapabilities,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SourceCapabilities {
+    pub search: bool,
+    pub filters: bool,
+    pub latest: bool,
+}
+
+impl SourceManifest {
+    // See Locale::matches, e.g.: a source in "es-419" supports "es"
+    pub fn supports_locale(&self, locale: &Locale) -> bool {
+        self.locales.iter().any(|l| l.matches(locale))
+    }
+}
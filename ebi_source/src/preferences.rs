@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SourceError;
+
+// User settings a source declares (e.g.: image quality, domain mirror). Values are stored
+// by the host and given to the source when it is initialized
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PreferenceSchema {
+    #[serde(default)]
+    pub preferences: Vec<Preference>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Preference {
+    pub key: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub kind: PreferenceKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PreferenceKind {
+    Toggle {
+        default: bool,
+    },
+    Text {
+        default: String,
+    },
+    Select {
+        options: Vec<String>,
+        default: String,
+    },
+    MultiSelect {
+        options: Vec<String>,
+        default: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PreferenceValue {
+    Toggle(bool),
+    Text(String),
+    List(Vec<String>),
+}
+
+// key => value
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Preferences {
+    values: HashMap<String, PreferenceValue>,
+}
+
+impl Preference {
+    pub fn default_value(&self) -> PreferenceValue {
+        match self.kind {
+            PreferenceKind::Toggle { default } => PreferenceValue::Toggle(default),
+            PreferenceKind::Text { ref default } | PreferenceKind::Select { ref default, .. } => {
+                PreferenceValue::Text(default.clone())
+            }
+            PreferenceKind::MultiSelect { ref default, .. } => {
+                PreferenceValue::List(default.clone())
+            }
+        }
+    }
+
+    pub fn accepts(&self, value: &PreferenceValue) -> bool {
+        match (&self.kind, value) {
+            (PreferenceKind::Toggle { .. }, PreferenceValue::Toggle(_)) => true,
+            (PreferenceKind::Text { .. }, PreferenceValue::Text(_)) => true,
+            (PreferenceKind::Select { options, .. }, PreferenceValue::Text(value)) => {
+                options.contains(value)
+            }
+            (PreferenceKind::MultiSelect { options, .. }, PreferenceValue::List(values)) => {
+                values.iter().all(|value| options.contains(value))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl PreferenceSchema {
+    pub fn preference(&self, key: &str) -> Option<&Preference> {
+        self.preferences.iter().find(|p| p.key == key)
+    }
+
+    // Keys are unique, and every default is a value the preference accepts
+    pub fn validate(&self) -> Result<(), SourceError> {
+        let invalid = |key: &str, reason: &str| {
+            Err(SourceError::InvalidPreferenceSchema(format!(
+                "{} :: {}",
+                key, reason
+            )))
+        };
+
+        for (i, preference) in self.preferences.iter().enumerate() {
+            let key = &preference.key;
+            if key.is_empty() {
+                return invalid(key, "empty key");
+            }
+            if self.preferences[..i].iter().any(|p| &p.key == key) {
+                return invalid(key, "duplicated key");
+            }
+
+            match preference.kind {
+                PreferenceKind::Select { ref options, .. }
+                | PreferenceKind::MultiSelect { ref options, .. }
+                    if options.is_empty() =>
+                {
+                    return invalid(key, "no options");
+                }
+                _ => {}
+            }
+            if !preference.accepts(&preference.default_value()) {
+                return invalid(key, "default is not one of its options");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn defaults(&self) -> Preferences {
+        let values = self
+            .preferences
+            .iter()
+            .map(|p| (p.key.clone(), p.default_value()))
+            .collect();
+        Preferences { values }
+    }
+
+    // Every key of the schema, stored values the schema no longer accepts fall back to defaults
+    pub fn resolve(&self, stored: &Preferences) -> Preferences {
+        let mut preferences = self.defaults();
+        for preference in self.preferences.iter() {
+            match stored.get(&preference.key) {
+                Some(value) if preference.accepts(value) => {
+                    preferences.set(&preference.key, value.clone())
+                }
+                _ => {}
+            }
+        }
+        preferences
+    }
+}
+
+impl Preferences {
+    pub fn get(&self, key: &str) -> Option<&PreferenceValue> {
+        self.values.get(key)
+    }
+
+    pub fn toggle(&self, key: &str) -> Option<bool> {
+        match self.values.get(key)? {
+            PreferenceValue::Toggle(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, key: &str) -> Option<&str> {
+        match self.values.get(key)? {
+            PreferenceValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn list(&self, key: &str) -> Option<&[String]> {
+        match self.values.get(key)? {
+            PreferenceValue::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: PreferenceValue) {
+        self.values.insert(key.to_owned(), value);
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<PreferenceValue> {
+        self.values.remove(key)
+    }
+
+    pub fn values(&self) -> &HashMap<String, PreferenceValue> {
+        &self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(key: &str, options: &[&str], default: &str) -> Preference {
+        Preference {
+            key: key.to_owned(),
+            title: key.to_owned(),
+            description: None,
+            kind: PreferenceKind::Select {
+                options: options.iter().map(|o| o.to_string()).collect(),
+                default: default.to_owned(),
+            },
+        }
+    }
+
+    fn schema(preferences: Vec<Preference>) -> PreferenceSchema {
+        PreferenceSchema { preferences }
+    }
+
+    #[test]
+    fn valid_schemas_pass() {
+        let quality = select("quality", &["low", "high"], "high");
+        let mirrors = Preference {
+            key: "mirrors".to_owned(),
+            title: "Mirrors".to_owned(),
+            description: None,
+            kind: PreferenceKind::MultiSelect {
+                options: vec!["a".to_owned(), "b".to_owned()],
+                default: vec![],
+            },
+        };
+        assert_eq!(schema(vec![quality, mirrors]).validate(), Ok(()));
+        assert_eq!(PreferenceSchema::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn select_defaults_must_be_an_option() {
+        assert_eq!(
+            schema(vec![select("quality", &["low", "high"], "max")]).validate(),
+            Err(SourceError::InvalidPreferenceSchema(
+                "quality :: default is not one of its options".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn keys_must_be_unique_and_selects_have_options() {
+        let duplicated = schema(vec![
+            select("quality", &["low"], "low"),
+            select("quality", &["high"], "high"),
+        ]);
+        assert!(duplicated.validate().is_err());
+        assert!(schema(vec![select("quality", &[], "")]).validate().is_err());
+        assert!(schema(vec![select("", &["low"], "low")])
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn stored_values_not_accepted_fall_back_to_defaults() {
+        let schema = schema(vec![select("quality", &["low", "high"], "high")]);
+        let mut stored = Preferences::default();
+        stored.set("quality", PreferenceValue::Text("max".to_owned()));
+        stored.set("removed", PreferenceValue::Toggle(true));
+
+        let resolved = schema.resolve(&stored);
+        assert_eq!(resolved.text("quality"), Some("high"));
+        assert_eq!(resolved.get("removed"), None);
+
+        stored.set("quality", PreferenceValue::Text("low".to_owned()));
+        assert_eq!(schema.resolve(&stored).text("quality"), Some("low"));
+    }
+}
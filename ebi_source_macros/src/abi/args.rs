@@ -1,6 +1,10 @@
 use proc_macro2::TokenStream;
 
+use super::signature::ExpectedSignature;
+
 pub trait GenArgsExt {
+    fn signature(&self) -> ExpectedSignature;
+
     fn args_list(&self) -> TokenStream {
         quote::quote! {}
     }
@@ -9,21 +13,53 @@ pub trait GenArgsExt {
         quote::quote! { ebi_source::abi::primitives::ABIResultArray }
     }
 
-    fn call(&self, name: &TokenStream) -> TokenStream {
-        quote::quote! { #name() }
+    // Converts the ABI arguments into the ones taken by the source function
+    fn args_parsing(&self) -> TokenStream {
+        quote::quote! {}
+    }
+
+    fn call_args(&self) -> TokenStream {
+        quote::quote! {}
+    }
+
+    // Arguments of the SourceLoader method, after `&self`
+    fn loader_call_args(&self) -> TokenStream {
+        self.call_args()
+    }
+
+    fn supports_async(&self) -> bool {
+        true
+    }
+
+    fn extra_items(&self) -> TokenStream {
+        quote::quote! {}
     }
 }
 
 pub struct SourceFunction;
 
 impl GenArgsExt for SourceFunction {
+    fn signature(&self) -> ExpectedSignature {
+        ExpectedSignature {
+            args: &[],
+            output: "Source",
+        }
+    }
+
     fn return_type(&self) -> TokenStream {
         quote::quote! { ebi_source::abi::source::source_info::ABISourceInfoOutput }
     }
 
-    fn call(&self, name: &TokenStream) -> TokenStream {
+    fn supports_async(&self) -> bool {
+        false
+    }
+
+    fn extra_items(&self) -> TokenStream {
         quote::quote! {
-            #name().into()
+            #[no_mangle]
+            pub extern "C" fn abi_version() -> u32 {
+                ebi_source::abi::ABI_VERSION
+            }
         }
     }
 }
@@ -31,27 +67,93 @@ impl GenArgsExt for SourceFunction {
 pub struct MangaListFunction;
 
 impl GenArgsExt for MangaListFunction {
-    fn call(&self, name: &TokenStream) -> TokenStream {
+    fn signature(&self) -> ExpectedSignature {
+        ExpectedSignature {
+            args: &[],
+            output: "Vec<Manga>",
+        }
+    }
+}
+
+pub struct SearchFunction;
+
+impl GenArgsExt for SearchFunction {
+    fn signature(&self) -> ExpectedSignature {
+        ExpectedSignature {
+            args: &[("query", "String")],
+            output: "Vec<Manga>",
+        }
+    }
+
+    fn args_list(&self) -> TokenStream {
+        quote::quote! { query: ebi_source::abi::primitives::FFIString }
+    }
+
+    fn args_parsing(&self) -> TokenStream {
         quote::quote! {
-            #name().into()
+            let query: ::std::string::String = match query.try_into() {
+                Ok(query) => query,
+                Err(e) => return ebi_source::abi::FromSourceError::from_source_error(e),
+            };
         }
     }
+
+    fn call_args(&self) -> TokenStream {
+        quote::quote! { query }
+    }
 }
 
 pub struct ChapterListFunction;
 
 impl GenArgsExt for ChapterListFunction {
+    fn signature(&self) -> ExpectedSignature {
+        ExpectedSignature {
+            args: &[
+                ("manga_identifier", "String"),
+                ("manga_url", "String"),
+                ("languages", "Vec<Locale>"),
+            ],
+            output: "Vec<Chapter>",
+        }
+    }
+
     fn args_list(&self) -> TokenStream {
         quote::quote! { manga: ebi_source::abi::chapter::chapter_list::ABIChapterListInput }
     }
 
-    fn call(&self, name: &TokenStream) -> TokenStream {
+    fn args_parsing(&self) -> TokenStream {
         quote::quote! {
-            // TODO: remove unwrap
-            let identifier = manga.identifier.try_into().unwrap();
-            let url = manga.url.try_into().unwrap();
+            let parsed = (|| {
+                ::std::result::Result::<_, ebi_source::error::SourceError>::Ok((
+                    manga.identifier.try_into()?,
+                    manga.url.try_into()?,
+                    ebi_source::abi::chapter::chapter_list::parse_languages(manga.languages)?,
+                ))
+            })();
+            let (identifier, url, languages) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => return ebi_source::abi::FromSourceError::from_source_error(e),
+            };
+        }
+    }
 
-            #name(identifier, url).into()
+    fn call_args(&self) -> TokenStream {
+        quote::quote! { identifier, url, languages }
+    }
+
+    // Only the identifier and url of the manga cross the ABI
+    fn loader_call_args(&self) -> TokenStream {
+        quote::quote! {
+            &ebi_source::Manga {
+                identifier,
+                url,
+                title: ::std::string::String::new(),
+                cover: ::std::string::String::new(),
+                genres: ::std::vec::Vec::new(),
+                description: None,
+                source: ::std::string::String::new(),
+            },
+            &languages
         }
     }
 }
@@ -59,19 +161,101 @@ impl GenArgsExt for ChapterListFunction {
 pub struct ChapterPageListFunction;
 
 impl GenArgsExt for ChapterPageListFunction {
+    fn signature(&self) -> ExpectedSignature {
+        ExpectedSignature {
+            args: &[
+                ("chapter", "f32"),
+                ("chapter_url", "String"),
+                ("manga", "String"),
+            ],
+            output: "Vec<String>",
+        }
+    }
+
     fn args_list(&self) -> TokenStream {
         quote::quote! { chapter: ebi_source::abi::chapter::chapter_page_list::ABIChapterPageListInput }
     }
 
-    fn call(&self, name: &TokenStream) -> TokenStream {
+    fn args_parsing(&self) -> TokenStream {
         quote::quote! {
-            // TODO: remove unwrap
-            let url = chapter.chapter_url.try_into().unwrap();
-            let manga = chapter.manga.try_into().unwrap();
+            let parsed = (|| {
+                ::std::result::Result::<_, ebi_source::error::SourceError>::Ok((
+                    chapter.chapter_url.try_into()?,
+                    chapter.manga.try_into()?,
+                ))
+            })();
+            let (url, manga) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => return ebi_source::abi::FromSourceError::from_source_error(e),
+            };
 
             let chapter = chapter.chapter;
+        }
+    }
+
+    fn call_args(&self) -> TokenStream {
+        quote::quote! { chapter, url, manga }
+    }
+
+    // Only the number, url and manga of the chapter cross the ABI
+    fn loader_call_args(&self) -> TokenStream {
+        quote::quote! {
+            &ebi_source::Chapter {
+                chapter,
+                url,
+                manga,
+                title: ::std::string::String::new(),
+                source: ::std::string::String::new(),
+                language: ebi_source::locale::Locale::UNKNOWN,
+            }
+        }
+    }
+}
+
+pub struct LoginFunction;
+
+impl GenArgsExt for LoginFunction {
+    fn signature(&self) -> ExpectedSignature {
+        ExpectedSignature {
+            args: &[("credentials", "Credentials")],
+            output: "()",
+        }
+    }
+
+    fn args_list(&self) -> TokenStream {
+        quote::quote! { credentials: ebi_source::abi::primitives::FFIString }
+    }
+
+    fn return_type(&self) -> TokenStream {
+        quote::quote! { ebi_source::abi::login::ABILoginOutput }
+    }
+
+    fn args_parsing(&self) -> TokenStream {
+        quote::quote! {
+            let credentials: ebi_source::credentials::Credentials =
+                match ebi_source::abi::json::decode(credentials) {
+                    Ok(credentials) => credentials,
+                    Err(e) => return ebi_source::abi::FromSourceError::from_source_error(e),
+                };
+        }
+    }
+
+    fn call_args(&self) -> TokenStream {
+        quote::quote! { credentials }
+    }
+}
 
-            #name(chapter, url, manga).into()
+pub struct LogoutFunction;
+
+impl GenArgsExt for LogoutFunction {
+    fn signature(&self) -> ExpectedSignature {
+        ExpectedSignature {
+            args: &[],
+            output: "()",
         }
     }
+
+    fn return_type(&self) -> TokenStream {
+        quote::quote! { ebi_source::abi::login::ABILoginOutput }
+    }
 }
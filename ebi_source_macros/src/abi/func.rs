@@ -3,40 +3,138 @@ use quote::ToTokens;
 
 use super::args::GenArgsExt;
 use super::args::{
-    ChapterListFunction, ChapterPageListFunction, MangaListFunction, SourceFunction,
+    ChapterListFunction, ChapterPageListFunction, LoginFunction, LogoutFunction, MangaListFunction,
+    SearchFunction, SourceFunction,
 };
 
 pub struct FnGenerator {
     name: TokenStream,
+    // Function getting the source state out of its handle, for `#[ebi_source]` methods
+    state: Option<TokenStream>,
+    // `<MySource as SourceLoader>::name`, for `#[ebi_source] impl SourceLoader for MySource`
+    loader: Option<TokenStream>,
     gen: Box<dyn GenArgsExt>,
 }
 
 impl FnGenerator {
+    // The state handle comes first, unused by free functions
     pub fn args_list(&self) -> TokenStream {
-        self.gen.args_list()
+        let args_list = self.gen.args_list();
+        match self.state {
+            Some(_) => quote::quote! {
+                __ebi_state: ebi_source::abi::lifecycle::ABIStateHandle, #args_list
+            },
+            None => quote::quote! { _: ebi_source::abi::lifecycle::ABIStateHandle, #args_list },
+        }
     }
 
-    // pub fn args_parsing(&self) -> TokenStream {
-    //     self.gen.args_parsing()
-    // }
+    pub fn args_parsing(&self) -> TokenStream {
+        self.gen.args_parsing()
+    }
 
     pub fn return_type(&self) -> TokenStream {
         self.gen.return_type()
     }
 
-    pub fn call(&self) -> TokenStream {
+    fn call_args(&self) -> TokenStream {
+        match self.loader {
+            Some(_) => {
+                let args = self.gen.loader_call_args();
+                quote::quote! { &state, #args }
+            }
+            None => self.gen.call_args(),
+        }
+    }
+
+    // `call` builds the source function call out of its path, methods are called on
+    // the state, unless it could not be initialized
+    fn invoke(&self, call: impl FnOnce(TokenStream) -> TokenStream) -> TokenStream {
         let name = &self.name;
-        self.gen.call(name)
+        match self.state {
+            Some(ref state) => {
+                let method = match self.loader {
+                    Some(ref loader) => loader.clone(),
+                    None => quote::quote! { state.#name },
+                };
+                let call = call(method);
+                quote::quote! {
+                    match #state(__ebi_state) {
+                        Ok(state) => #call,
+                        Err(e) => Err(e),
+                    }
+                }
+            }
+            None => call(name.clone()),
+        }
+    }
+
+    // Panics must not unwind into the host, through the `extern "C"` wrapper
+    pub fn call(&self) -> TokenStream {
+        let args = self.call_args();
+        let call = self.invoke(|f| quote::quote! { #f(#args) });
+        quote::quote! { ebi_source::abi::future::catch_panic(|| (#call).into()) }
+    }
+
+    // Blocking call of an async source function, whose panics (e.g.: on a missing
+    // runtime) must not unwind into the host either
+    pub fn block_on_call(&self) -> TokenStream {
+        let args = self.call_args();
+        let call = self.invoke(|f| quote::quote! { ebi_source::abi::future::block_on(#f(#args)) });
+        quote::quote! { ebi_source::abi::future::catch_panic(|| (#call).into()) }
+    }
+
+    pub fn async_return_type(&self) -> TokenStream {
+        let return_type = self.return_type();
+        quote::quote! { ebi_source::abi::future::FFIFuture<#return_type> }
+    }
+
+    pub fn async_call(&self) -> TokenStream {
+        let args = self.call_args();
+        let call = self.invoke(|f| quote::quote! { #f(#args).await });
+        quote::quote! {
+            ebi_source::abi::future::FFIFuture::new(async move { (#call).into() })
+        }
+    }
+
+    // SourceLoader methods are checked by the compiler against the trait instead
+    pub fn validate(&self, signature: &syn::Signature) -> Result<(), syn::Error> {
+        if self.loader.is_some() {
+            return Ok(());
+        }
+        self.gen
+            .signature()
+            .validate(signature, self.state.is_some())
+    }
+
+    pub fn supports_async(&self) -> bool {
+        self.gen.supports_async()
+    }
+
+    pub fn extra_items(&self) -> TokenStream {
+        self.gen.extra_items()
     }
 }
 
 pub enum AbiFns {
     SourceInfo,
     MangaList,
+    Search,
     ChapterList,
     ChapterPageList,
+    Login,
+    Logout,
 }
 
+const ABI_FN_NAMES: [&str; 7] = [
+    "source_info",
+    "manga_list",
+    "search",
+    "chapter_list",
+    "chapter_page_list",
+    "login",
+    "logout",
+];
+
 impl TryFrom<&Ident> for AbiFns {
     type Error = syn::Error;
 
@@ -44,14 +142,46 @@ impl TryFrom<&Ident> for AbiFns {
         match name.to_string().as_str() {
             "source_info" => Ok(Self::SourceInfo),
             "manga_list" => Ok(Self::MangaList),
+            "search" => Ok(Self::Search),
             "chapter_list" => Ok(Self::ChapterList),
             "chapter_page_list" => Ok(Self::ChapterPageList),
-            _ => Err(syn::Error::new(name.span(), "Invalid function name")),
+            "login" => Ok(Self::Login),
+            "logout" => Ok(Self::Logout),
+            _ => Err(syn::Error::new(
+                name.span(),
+                format!(
+                    "Invalid function name `{}`, expected one of: {}",
+                    name,
+                    ABI_FN_NAMES.join(", ")
+                ),
+            )),
         }
     }
 }
 
+pub const REQUIRED_ABI_FNS: [&str; 2] = ["source_info", "manga_list"];
+
 impl AbiFns {
+    pub fn is_abi_fn(name: &Ident) -> bool {
+        ABI_FN_NAMES.contains(&name.to_string().as_str())
+    }
+
+    pub fn method_generator(name: &Ident, state: TokenStream) -> Result<FnGenerator, syn::Error> {
+        let mut generator = Self::generator(name)?;
+        generator.state = Some(state);
+        Ok(generator)
+    }
+
+    pub fn loader_method_generator(
+        name: &Ident,
+        state: TokenStream,
+        self_ty: &syn::Type,
+    ) -> Result<FnGenerator, syn::Error> {
+        let mut generator = Self::method_generator(name, state)?;
+        generator.loader = Some(quote::quote! { <#self_ty as ebi_source::SourceLoader>::#name });
+        Ok(generator)
+    }
+
     pub fn generator(name: &Ident) -> Result<FnGenerator, syn::Error> {
         let abi_func = Self::try_from(name)?;
         let name = name.to_token_stream();
@@ -61,10 +191,15 @@ impl AbiFns {
             Self::ChapterPageList => Box::new(ChapterPageListFunction {}),
             Self::SourceInfo => Box::new(SourceFunction {}),
             Self::MangaList => Box::new(MangaListFunction {}),
+            Self::Search => Box::new(SearchFunction {}),
+            Self::Login => Box::new(LoginFunction {}),
+            Self::Logout => Box::new(LogoutFunction {}),
         };
 
         Ok(FnGenerator {
             name,
+            state: None,
+            loader: None,
             gen: generator,
         })
     }
@@ -1,34 +1,418 @@
 mod args;
 mod func;
+mod signature;
 
-use self::func::{AbiFns, FnGenerator};
-use syn::{Ident, Signature};
+use self::func::{AbiFns, FnGenerator, REQUIRED_ABI_FNS};
+use self::signature::{type_matches, ExpectedSignature};
+use proc_macro2::TokenStream;
+use syn::spanned::Spanned;
+use syn::{FnArg, Ident, ImplItem, ItemImpl, Signature};
 
-fn abi_ident(ident: &Ident) -> proc_macro2::TokenStream {
-    let abi_fn_name = format!("abi_{}", ident.to_string());
+fn abi_ident(ident: &Ident) -> TokenStream {
+    let abi_fn_name = format!("abi_{}", ident);
     abi_fn_name.parse().unwrap()
 }
 
-fn abi_fn_from_generator(
-    gen: FnGenerator,
-    abi_fn_ident: proc_macro2::TokenStream,
-) -> proc_macro::TokenStream {
+fn abi_async_ident(ident: &Ident) -> TokenStream {
+    let abi_fn_name = format!("abi_{}_async", ident);
+    abi_fn_name.parse().unwrap()
+}
+
+fn abi_fn_from_generator(gen: FnGenerator, abi_fn_ident: TokenStream) -> TokenStream {
     let return_type = gen.return_type();
     let arg_list = gen.args_list();
+    let args_parsing = gen.args_parsing();
     let call = gen.call();
+    let extra_items = gen.extra_items();
 
     quote::quote! {
         #[no_mangle]
         pub extern "C" fn #abi_fn_ident(#arg_list) -> #return_type {
+            #args_parsing
             #call
         }
+
+        #extra_items
     }
-    .into()
 }
 
-pub fn gen_abi_fn(signature: &Signature) -> Result<proc_macro::TokenStream, syn::Error> {
+// Async functions are exported twice: as a blocking function, so hosts without async
+// support can still call them, and as abi_<name>_async returning an FFIFuture
+fn abi_async_fn_from_generator(
+    gen: FnGenerator,
+    abi_fn_ident: TokenStream,
+    abi_async_fn_ident: TokenStream,
+) -> TokenStream {
+    let return_type = gen.return_type();
+    let async_return_type = gen.async_return_type();
+    let arg_list = gen.args_list();
+    let args_parsing = gen.args_parsing();
+    let block_on_call = gen.block_on_call();
+    let async_call = gen.async_call();
+    let extra_items = gen.extra_items();
+
+    quote::quote! {
+        #[no_mangle]
+        pub extern "C" fn #abi_fn_ident(#arg_list) -> #return_type {
+            #args_parsing
+            #block_on_call
+        }
+
+        #[no_mangle]
+        pub extern "C" fn #abi_async_fn_ident(#arg_list) -> #async_return_type {
+            #args_parsing
+            #async_call
+        }
+
+        #extra_items
+    }
+}
+
+fn abi_fn_from_signature(
+    signature: &Signature,
+    fn_gen: FnGenerator,
+) -> Result<TokenStream, syn::Error> {
     let name = &signature.ident;
     let abi_fn_ident = abi_ident(name);
-    let fn_gen = AbiFns::generator(name)?;
-    Ok(abi_fn_from_generator(fn_gen, abi_fn_ident))
+    fn_gen.validate(signature)?;
+
+    match signature.asyncness {
+        Some(asyncness) if !fn_gen.supports_async() => Err(syn::Error::new(
+            asyncness.span,
+            format!("{} can't be async", name),
+        )),
+        Some(_) => Ok(abi_async_fn_from_generator(
+            fn_gen,
+            abi_fn_ident,
+            abi_async_ident(name),
+        )),
+        None => Ok(abi_fn_from_generator(fn_gen, abi_fn_ident)),
+    }
+}
+
+pub fn gen_abi_fn(signature: &Signature) -> Result<TokenStream, syn::Error> {
+    let fn_gen = AbiFns::generator(&signature.ident)?;
+    abi_fn_from_signature(signature, fn_gen)
+}
+
+fn combine(errors: Vec<syn::Error>) -> Result<(), syn::Error> {
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        Some(mut first) => {
+            errors.for_each(|e| first.combine(e));
+            Err(first)
+        }
+        None => Ok(()),
+    }
+}
+
+// `fn shutdown(&self)`
+fn validate_shutdown(signature: &Signature) -> Result<(), syn::Error> {
+    let takes_ref_self = match signature.inputs.first() {
+        Some(FnArg::Receiver(receiver)) => {
+            receiver.reference.is_some() && receiver.mutability.is_none()
+        }
+        _ => false,
+    };
+
+    match takes_ref_self
+        && signature.inputs.len() == 1
+        && signature.asyncness.is_none()
+        && matches!(signature.output, syn::ReturnType::Default)
+    {
+        true => Ok(()),
+        false => Err(syn::Error::new(
+            signature.span(),
+            "shutdown must be `fn shutdown(&self)`",
+        )),
+    }
+}
+
+// `fn set_cookie_jar(&self, jar: CookieJar)`
+fn validate_set_cookie_jar(signature: &Signature) -> Result<(), syn::Error> {
+    let mut inputs = signature.inputs.iter();
+    let takes_ref_self = match inputs.next() {
+        Some(FnArg::Receiver(receiver)) => {
+            receiver.reference.is_some() && receiver.mutability.is_none()
+        }
+        _ => false,
+    };
+    let takes_jar = match inputs.next() {
+        Some(FnArg::Typed(arg)) => match *arg.ty {
+            syn::Type::Path(ref path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "CookieJar"),
+            _ => false,
+        },
+        _ => false,
+    };
+
+    match takes_ref_self
+        && takes_jar
+        && signature.inputs.len() == 2
+        && signature.asyncness.is_none()
+        && matches!(signature.output, syn::ReturnType::Default)
+    {
+        true => Ok(()),
+        false => Err(syn::Error::new(
+            signature.span(),
+            "set_cookie_jar must be `fn set_cookie_jar(&self, jar: CookieJar)`",
+        )),
+    }
+}
+
+// `fn preferences_schema() -> PreferenceSchema`
+fn validate_preferences_schema(signature: &Signature) -> Result<(), syn::Error> {
+    let returns_schema = match signature.output {
+        syn::ReturnType::Type(_, ref ty) => match **ty {
+            syn::Type::Path(ref path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "PreferenceSchema"),
+            _ => false,
+        },
+        syn::ReturnType::Default => false,
+    };
+
+    match returns_schema && signature.inputs.is_empty() && signature.asyncness.is_none() {
+        true => Ok(()),
+        false => Err(syn::Error::new(
+            signature.span(),
+            "preferences_schema must be `fn preferences_schema() -> PreferenceSchema`",
+        )),
+    }
+}
+
+// `type Error = SourceError;`, as errors go through the ABI as SourceError
+fn validate_loader_error(item: &ItemImpl) -> Result<(), syn::Error> {
+    let error = item.items.iter().find_map(|item| match item {
+        ImplItem::Type(ty) if ty.ident == "Error" => Some(&ty.ty),
+        _ => None,
+    });
+
+    match error {
+        Some(ty) if !type_matches(ty, "SourceError") => Err(syn::Error::new(
+            ty.span(),
+            "SourceLoader::Error must be `SourceError`",
+        )),
+        // A missing Error is reported by the compiler
+        _ => Ok(()),
+    }
+}
+
+// Every ABI function of an `#[ebi_source]` impl is a method called on a single instance
+// of the source, created by `init` (or `Default`) in abi_init and handed to the host, which
+// passes it back to every function and to abi_shutdown. Optional functions that are not
+// implemented are not exported, which is how the host knows they are unsupported.
+// `init` may take the stored preferences, resolved against `preferences_schema`.
+// `set_cookie_jar` is called once, right after `init`, with the host cookie store.
+// `impl SourceLoader for MySource` only exports the SourceLoader functions, with the
+// state created by `Default`: optional functions and `init` need an inherent impl
+pub fn gen_abi_impl(item: &ItemImpl) -> Result<TokenStream, syn::Error> {
+    let loader = match item.trait_ {
+        Some((_, ref path, _))
+            if path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "SourceLoader") =>
+        {
+            validate_loader_error(item)?;
+            true
+        }
+        Some((_, ref path, _)) => {
+            return Err(syn::Error::new(
+                path.span(),
+                "#[ebi_source] must be used on an inherent impl block (impl MySource { .. }) \
+                 or on `impl SourceLoader for MySource`",
+            ))
+        }
+        None => false,
+    };
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            item.generics.span(),
+            "#[ebi_source] can't be used on generic types",
+        ));
+    }
+
+    let self_ty = &item.self_ty;
+    let state = quote::quote! { __ebi_source_state };
+
+    let mut errors = Vec::new();
+    let mut abi_fns = Vec::new();
+    let mut implemented = Vec::new();
+    let mut init = quote::quote! { Ok(<#self_ty as ::std::default::Default>::default()) };
+    let mut shutdown = quote::quote! {};
+    let mut schema = quote::quote! { ebi_source::preferences::PreferenceSchema::default() };
+    let mut schema_fn = quote::quote! {};
+    let mut cookie_jar_fn = quote::quote! {};
+
+    for method in item.items.iter().filter_map(|item| match item {
+        ImplItem::Method(method) => Some(method),
+        _ => None,
+    }) {
+        let signature = &method.sig;
+        let name = &signature.ident;
+
+        if AbiFns::is_abi_fn(name) {
+            implemented.push(name.to_string());
+            let fn_gen = match loader {
+                true => AbiFns::loader_method_generator(name, state.clone(), self_ty),
+                false => AbiFns::method_generator(name, state.clone()),
+            };
+            match fn_gen.and_then(|fn_gen| abi_fn_from_signature(signature, fn_gen)) {
+                Ok(abi_fn) => abi_fns.push(abi_fn),
+                Err(e) => errors.push(e),
+            }
+        } else if name == "init" {
+            let (expected, call) = match signature.inputs.len() {
+                0 => (
+                    ExpectedSignature {
+                        args: &[],
+                        output: "Self",
+                    },
+                    quote::quote! { <#self_ty>::init() },
+                ),
+                _ => (
+                    ExpectedSignature {
+                        args: &[("preferences", "Preferences")],
+                        output: "Self",
+                    },
+                    quote::quote! { <#self_ty>::init(preferences) },
+                ),
+            };
+            match expected.validate(signature, false) {
+                Ok(()) => init = call,
+                Err(e) => errors.push(e),
+            }
+        } else if name == "preferences_schema" {
+            match validate_preferences_schema(signature) {
+                Ok(()) => {
+                    schema = quote::quote! { <#self_ty>::preferences_schema() };
+                    schema_fn = quote::quote! {
+                        #[no_mangle]
+                        pub extern "C" fn abi_preferences_schema(
+                        ) -> ebi_source::abi::primitives::FFIString {
+                            // A null schema fails the load
+                            ebi_source::abi::future::catch_panic_with(
+                                || ebi_source::abi::json::encode(&__ebi_preferences_schema()),
+                                |_| ebi_source::abi::primitives::FFIString::null(),
+                            )
+                        }
+                    };
+                }
+                Err(e) => errors.push(e),
+            }
+        } else if name == "set_cookie_jar" {
+            match validate_set_cookie_jar(signature) {
+                Ok(()) => {
+                    cookie_jar_fn = quote::quote! {
+                        #[no_mangle]
+                        pub extern "C" fn abi_set_cookie_jar(
+                            handle: ebi_source::abi::lifecycle::ABIStateHandle,
+                            jar: ebi_source::abi::cookies::ABICookieJar,
+                        ) {
+                            if let Ok(state) = #state(handle) {
+                                ebi_source::abi::future::catch_panic_with(
+                                    || state.set_cookie_jar(
+                                        ebi_source::cookies::CookieJar::from(jar),
+                                    ),
+                                    |message| eprintln!("set_cookie_jar panicked :: {}", message),
+                                );
+                            }
+                        }
+                    };
+                }
+                Err(e) => errors.push(e),
+            }
+        } else if name == "shutdown" {
+            match validate_shutdown(signature) {
+                Ok(()) => shutdown = quote::quote! { state.shutdown(); },
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+
+    for required in REQUIRED_ABI_FNS {
+        if !implemented.iter().any(|name| name == required) {
+            errors.push(syn::Error::new(
+                self_ty.span(),
+                format!("missing `{}`, required by every source", required),
+            ));
+        }
+    }
+    combine(errors)?;
+
+    Ok(quote::quote! {
+        const _: () = {
+            // Shared across the threads calls come from
+            fn __ebi_assert_state<T: ::std::marker::Send + ::std::marker::Sync>() {}
+
+            fn #state(
+                handle: ebi_source::abi::lifecycle::ABIStateHandle,
+            ) -> ::std::result::Result<
+                ::std::sync::Arc<#self_ty>,
+                ebi_source::error::SourceError,
+            > {
+                // The host only passes back handles made by abi_init, until abi_shutdown
+                unsafe { handle.clone_arc::<#self_ty>() }
+                    .ok_or(ebi_source::error::SourceError::Uninitialized)
+            }
+
+            fn __ebi_preferences_schema() -> ebi_source::preferences::PreferenceSchema {
+                #schema
+            }
+
+            #schema_fn
+
+            #[allow(unused_variables)]
+            fn __ebi_source_init(
+                preferences: ebi_source::preferences::Preferences,
+            ) -> ::std::result::Result<#self_ty, ebi_source::error::SourceError> {
+                #init
+            }
+
+            #[no_mangle]
+            pub extern "C" fn abi_init(
+                preferences: ebi_source::abi::primitives::FFIString,
+            ) -> ebi_source::abi::lifecycle::ABIInitOutput {
+                __ebi_assert_state::<#self_ty>();
+                // A panicking init is reported as an init error
+                ebi_source::abi::future::catch_panic(|| {
+                    // Stored values are only a starting point, the schema may have changed since
+                    let stored: ebi_source::preferences::Preferences =
+                        ebi_source::abi::json::decode(preferences).unwrap_or_default();
+                    let preferences = __ebi_preferences_schema().resolve(&stored);
+
+                    __ebi_source_init(preferences)
+                        .map(|state| {
+                            ebi_source::abi::lifecycle::ABIStateHandle::from_arc(
+                                ::std::sync::Arc::new(state),
+                            )
+                        })
+                        .into()
+                })
+            }
+
+            // The source is being unloaded, so a panic is only reported
+            #[no_mangle]
+            pub extern "C" fn abi_shutdown(handle: ebi_source::abi::lifecycle::ABIStateHandle) {
+                ebi_source::abi::future::catch_panic_with(
+                    || {
+                        if let Some(state) = unsafe { handle.into_arc::<#self_ty>() } {
+                            #shutdown
+                        }
+                    },
+                    |message| eprintln!("shutdown panicked :: {}", message),
+                );
+            }
+
+            #cookie_jar_fn
+
+            #(#abi_fns)*
+        };
+    })
 }
@@ -0,0 +1,201 @@
+use syn::spanned::Spanned;
+use syn::{FnArg, GenericArgument, PathArguments, ReturnType, Signature, Type};
+
+// Signature a source function must have to be exported through the ABI
+pub struct ExpectedSignature {
+    // (name, type), names are only used in diagnostics
+    pub args: &'static [(&'static str, &'static str)],
+    // T in Result<T, SourceError>
+    pub output: &'static str,
+}
+
+impl ExpectedSignature {
+    fn display(&self, name: &str, method: bool) -> String {
+        let receiver = method.then(|| "&self".to_owned());
+        let args = receiver
+            .into_iter()
+            .chain(
+                self.args
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty)),
+            )
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            "fn {}({}) -> Result<{}, SourceError>",
+            name, args, self.output
+        )
+    }
+
+    // Reports every mismatch at once, each one pointing at the offending part.
+    // Methods (`#[ebi_source]`) must take `&self` first
+    pub fn validate(&self, signature: &Signature, method: bool) -> Result<(), syn::Error> {
+        let name = signature.ident.to_string();
+        let mut errors: Vec<syn::Error> = Vec::new();
+        let mut error = |span, message: String| {
+            errors.push(syn::Error::new(
+                span,
+                format!("{}\nexpected: `{}`", message, self.display(&name, method)),
+            ))
+        };
+
+        if !signature.generics.params.is_empty() {
+            error(
+                signature.generics.span(),
+                format!("{} can't be generic", name),
+            );
+        }
+
+        let mut inputs = signature.inputs.iter().peekable();
+        if method {
+            match inputs.peek() {
+                Some(FnArg::Receiver(receiver))
+                    if receiver.reference.is_some() && receiver.mutability.is_none() =>
+                {
+                    inputs.next();
+                }
+                Some(FnArg::Receiver(receiver)) => {
+                    error(receiver.span(), format!("{} must take &self", name));
+                    inputs.next();
+                }
+                _ => error(
+                    signature.paren_token.span,
+                    format!("{} must take &self", name),
+                ),
+            }
+        }
+
+        let inputs: Vec<&FnArg> = inputs.collect();
+        if inputs.len() != self.args.len() {
+            error(
+                signature.paren_token.span,
+                format!(
+                    "{} takes {} argument(s), found {}",
+                    name,
+                    self.args.len(),
+                    inputs.len()
+                ),
+            );
+        }
+
+        for (input, (arg_name, expected)) in inputs.into_iter().zip(self.args.iter()) {
+            match input {
+                FnArg::Receiver(receiver) => {
+                    error(receiver.span(), format!("{} can't take self", name))
+                }
+                FnArg::Typed(arg) if !type_matches(&arg.ty, expected) => error(
+                    arg.ty.span(),
+                    format!("{} must be `{}`", arg_name, expected),
+                ),
+                FnArg::Typed(_) => {}
+            }
+        }
+
+        match signature.output {
+            ReturnType::Default => error(
+                signature.ident.span(),
+                format!(
+                    "{} must return `Result<{}, SourceError>`",
+                    name, self.output
+                ),
+            ),
+            ReturnType::Type(_, ref ty) => match result_types(ty) {
+                Some((ok, err)) => {
+                    if !type_matches(ok, self.output) {
+                        error(ok.span(), format!("Ok type must be `{}`", self.output));
+                    }
+                    if !type_matches(err, "SourceError") {
+                        error(err.span(), "Err type must be `SourceError`".to_owned());
+                    }
+                }
+                None => error(
+                    ty.span(),
+                    format!(
+                        "{} must return `Result<{}, SourceError>`",
+                        name, self.output
+                    ),
+                ),
+            },
+        }
+
+        let mut errors = errors.into_iter();
+        match errors.next() {
+            Some(mut first) => {
+                errors.for_each(|e| first.combine(e));
+                Err(first)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+// (T, E) of a Result<T, E>
+fn result_types(ty: &Type) -> Option<(&Type, &Type)> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Result" {
+        return None;
+    }
+
+    match generic_types(&segment.arguments).as_slice() {
+        [ok, err] => Some((ok, err)),
+        _ => None,
+    }
+}
+
+fn generic_types(arguments: &PathArguments) -> Vec<&Type> {
+    match arguments {
+        PathArguments::AngleBracketed(arguments) => arguments
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Compares the last path segments only, so `String` and `std::string::String` both match
+pub fn type_matches(ty: &Type, expected: &str) -> bool {
+    let expected: Type = match syn::parse_str(expected) {
+        Ok(expected) => expected,
+        Err(_) => return false,
+    };
+    same_type(ty, &expected)
+}
+
+fn same_type(ty: &Type, expected: &Type) -> bool {
+    let (segment, expected) = match (ty, expected) {
+        (Type::Path(ty), Type::Path(expected)) if ty.qself.is_none() => {
+            match (ty.path.segments.last(), expected.path.segments.last()) {
+                (Some(segment), Some(expected)) => (segment, expected),
+                _ => return false,
+            }
+        }
+        (Type::Tuple(ty), Type::Tuple(expected)) => {
+            return ty.elems.len() == expected.elems.len()
+                && ty
+                    .elems
+                    .iter()
+                    .zip(expected.elems.iter())
+                    .all(|(ty, expected)| same_type(ty, expected));
+        }
+        (Type::Group(ty), _) => return same_type(&ty.elem, expected),
+        (Type::Paren(ty), _) => return same_type(&ty.elem, expected),
+        _ => return false,
+    };
+
+    let args = generic_types(&segment.arguments);
+    let expected_args = generic_types(&expected.arguments);
+
+    segment.ident == expected.ident
+        && args.len() == expected_args.len()
+        && args
+            .iter()
+            .zip(expected_args.iter())
+            .all(|(ty, expected)| same_type(ty, expected))
+}
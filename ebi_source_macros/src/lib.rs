@@ -1,5 +1,5 @@
 use proc_macro::TokenStream;
-use syn::ItemFn;
+use syn::{ItemFn, ItemImpl};
 
 mod abi;
 
@@ -16,7 +16,24 @@ pub fn ebi_plugin(_: TokenStream, fn_body: TokenStream) -> TokenStream {
     };
 
     let mut input = fn_body;
-    input.extend(abi_fn);
+    input.extend(TokenStream::from(abi_fn));
+    input
+}
+
+#[proc_macro_attribute]
+pub fn ebi_source(_: TokenStream, impl_body: TokenStream) -> TokenStream {
+    let ast = match syn::parse::<ItemImpl>(impl_body.clone()) {
+        Ok(ast) => ast,
+        Err(err) => return input_and_compile_error(impl_body, err),
+    };
+
+    let abi_impl = match abi::gen_abi_impl(&ast) {
+        Ok(abi_impl) => abi_impl,
+        Err(err) => return input_and_compile_error(impl_body, err),
+    };
+
+    let mut input = impl_body;
+    input.extend(TokenStream::from(abi_impl));
     input
 }
 
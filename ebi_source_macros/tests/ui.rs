@@ -0,0 +1,14 @@
+// Compile errors reported by the macros, see tests/ui/*/*.stderr.
+// Regenerate the expected output with TRYBUILD=overwrite
+#[test]
+fn plugin_diagnostics() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/plugin/*.rs");
+}
+
+#[test]
+fn source_diagnostics() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/source/*.rs");
+    t.pass("tests/ui/source_pass/*.rs");
+}
@@ -0,0 +1,10 @@
+use ebi_source::error::SourceError;
+use ebi_source::Source;
+use ebi_source_macros::ebi_plugin;
+
+#[ebi_plugin]
+pub async fn source_info() -> Result<Source, SourceError> {
+    Err(SourceError::Fetch)
+}
+
+fn main() {}
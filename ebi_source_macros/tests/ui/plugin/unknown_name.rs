@@ -0,0 +1,10 @@
+use ebi_source::error::SourceError;
+use ebi_source::Manga;
+use ebi_source_macros::ebi_plugin;
+
+#[ebi_plugin]
+pub fn mangas() -> Result<Vec<Manga>, SourceError> {
+    Ok(vec![])
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use ebi_source::error::SourceError;
+use ebi_source::Chapter;
+use ebi_source_macros::ebi_plugin;
+
+#[ebi_plugin]
+pub fn chapter_list(manga_identifier: &str, manga_url: String) -> Result<Vec<Chapter>, SourceError> {
+    let _ = (manga_identifier, manga_url);
+    Ok(vec![])
+}
+
+fn main() {}
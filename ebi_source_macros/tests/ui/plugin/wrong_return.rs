@@ -0,0 +1,9 @@
+use ebi_source::Manga;
+use ebi_source_macros::ebi_plugin;
+
+#[ebi_plugin]
+pub fn manga_list() -> Result<Manga, String> {
+    Err(String::new())
+}
+
+fn main() {}
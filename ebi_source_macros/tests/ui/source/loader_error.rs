@@ -0,0 +1,29 @@
+use ebi_source::locale::Locale;
+use ebi_source::{Chapter, Manga, Source, SourceLoader};
+use ebi_source_macros::ebi_source;
+
+#[derive(Default)]
+pub struct MySource;
+
+#[ebi_source]
+impl SourceLoader for MySource {
+    type Error = String;
+
+    fn source_info(&self) -> Result<Source, String> {
+        Err(String::new())
+    }
+
+    fn manga_list(&self) -> Result<Vec<Manga>, String> {
+        Ok(vec![])
+    }
+
+    fn chapter_list(&self, _: &Manga, _: &[Locale]) -> Result<Vec<Chapter>, String> {
+        Ok(vec![])
+    }
+
+    fn chapter_page_list(&self, _: &Chapter) -> Result<Vec<String>, String> {
+        Ok(vec![])
+    }
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use ebi_source::error::SourceError;
+use ebi_source::{Manga, Source};
+use ebi_source_macros::ebi_source;
+
+#[derive(Default)]
+pub struct MySource;
+
+#[ebi_source]
+impl MySource {
+    fn source_info(&self) -> Result<Source, SourceError> {
+        Err(SourceError::Fetch)
+    }
+
+    fn manga_list() -> Result<Vec<Manga>, SourceError> {
+        Ok(vec![])
+    }
+
+    fn shutdown(&mut self) {}
+}
+
+fn main() {}
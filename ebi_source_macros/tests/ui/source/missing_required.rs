@@ -0,0 +1,15 @@
+use ebi_source::error::SourceError;
+use ebi_source::Source;
+use ebi_source_macros::ebi_source;
+
+#[derive(Default)]
+pub struct MySource;
+
+#[ebi_source]
+impl MySource {
+    fn source_info(&self) -> Result<Source, SourceError> {
+        Err(SourceError::Fetch)
+    }
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use ebi_source_macros::ebi_source;
+
+#[derive(Default)]
+pub struct MySource;
+
+#[ebi_source]
+impl Clone for MySource {
+    fn clone(&self) -> Self {
+        MySource
+    }
+}
+
+fn main() {}
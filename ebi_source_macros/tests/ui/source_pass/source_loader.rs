@@ -0,0 +1,43 @@
+use ebi_source::error::SourceError;
+use ebi_source::locale::Locale;
+use ebi_source::{Chapter, Manga, Source, SourceLoader};
+use ebi_source_macros::ebi_source;
+
+#[derive(Default)]
+pub struct LoaderSource;
+
+#[ebi_source]
+impl SourceLoader for LoaderSource {
+    type Error = SourceError;
+
+    fn source_info(&self) -> Result<Source, SourceError> {
+        Ok(Source {
+            identifier: "loader_source".to_owned(),
+            title: "Loader source".to_owned(),
+            description: String::new(),
+            locale: Locale::EN_US,
+            locales: vec![],
+        })
+    }
+
+    fn manga_list(&self) -> Result<Vec<Manga>, SourceError> {
+        Ok(vec![])
+    }
+
+    fn chapter_list(&self, manga: &Manga, _: &[Locale]) -> Result<Vec<Chapter>, SourceError> {
+        Ok(vec![Chapter {
+            chapter: 1.0,
+            title: String::new(),
+            url: format!("{}/1", manga.url),
+            manga: manga.identifier.clone(),
+            source: "loader_source".to_owned(),
+            language: Locale::EN_US,
+        }])
+    }
+
+    fn chapter_page_list(&self, chapter: &Chapter) -> Result<Vec<String>, SourceError> {
+        Ok(vec![format!("{}/1.png", chapter.url)])
+    }
+}
+
+fn main() {}
@@ -1,54 +1,189 @@
+use std::sync::{Mutex, OnceLock};
+
+use ebi_source::cookies::CookieJar;
+use ebi_source::credentials::Credentials;
 use ebi_source::error::SourceError;
-use ebi_source::{locale, Chapter, Manga, Source};
-use ebi_source_macros::ebi_plugin;
+use ebi_source::locale::Locale;
+use ebi_source::preferences::{Preference, PreferenceKind, PreferenceSchema, Preferences};
+use ebi_source::{Chapter, Manga, Source};
+use ebi_source_macros::ebi_source;
 
 const SOURCE_IDENTIFIER: &str = "valid_source_macro_mock";
 const SOURCE_TITLE: &str = "Mocked Valid Ebi Extension";
+const SOURCE_URL: &str = "http://127.0.0.1/";
 const SOURCE_DESCRIPTION: &str =
     "This is just a mocked source only intended to be used for tests! No real content here";
 
-#[ebi_plugin]
-pub fn source_info() -> Result<Source, SourceError> {
-    Ok(Source {
-        identifier: SOURCE_IDENTIFIER.to_owned(),
-        title: SOURCE_TITLE.to_owned(),
-        description: SOURCE_DESCRIPTION.to_owned(),
-        locale: locale::Locale::EnUs,
-    })
+// Created once when the source is loaded, shared by every call
+pub struct ExampleSource {
+    manga: Vec<Manga>,
+    image_quality: String,
+    // Logged in username
+    session: Mutex<Option<String>>,
+    // Host cookie store, shared with page downloads
+    cookies: OnceLock<CookieJar>,
 }
 
-#[ebi_plugin]
-pub fn manga_list() -> Result<Vec<Manga>, SourceError> {
-    let manga = vec![Manga {
-        identifier: "one-piece".to_string(),
-        title: "One Piece".to_string(),
-        cover: "http://127.0.0.1/fake-cover/one-piece".to_string(),
-        genres: vec!["shounen".to_string(), "fantasy".to_string()],
-        description: Some("Rubber pirate boy adventures".to_string()),
-        url: "/manga/one-piece".to_string(),
-        source: SOURCE_IDENTIFIER.to_string(),
-    }];
-    Ok(manga)
-}
+#[ebi_source]
+impl ExampleSource {
+    fn preferences_schema() -> PreferenceSchema {
+        PreferenceSchema {
+            preferences: vec![Preference {
+                key: "image_quality".to_owned(),
+                title: "Image quality".to_owned(),
+                description: None,
+                kind: PreferenceKind::Select {
+                    options: vec!["low".to_owned(), "high".to_owned()],
+                    default: "high".to_owned(),
+                },
+            }],
+        }
+    }
 
-#[ebi_plugin]
-pub fn chapter_list(
-    manga_identifier: String,
-    manga_url: String,
-) -> Result<Vec<Chapter>, SourceError> {
-    Ok(get_chapters(&manga_identifier, &manga_url, 100))
-}
-
-fn get_chapters(identifier: &str, url: &str, size: u32) -> Vec<Chapter> {
-    let manga = manga_list().unwrap();
-    let manga = manga.iter().find(|m| m.identifier == identifier).unwrap();
-    (1..size + 1)
-        .map(|chapter| Chapter {
-            chapter,
-            title: format!("{} -- {}", &manga.title, chapter),
-            url: format!("{}/{}", url, chapter),
-            manga: identifier.to_string(),
+    fn init(preferences: Preferences) -> Result<Self, SourceError> {
+        let manga = vec![Manga {
+            identifier: "one-piece".to_string(),
+            title: "One Piece".to_string(),
+            cover: "http://127.0.0.1/fake-cover/one-piece".to_string(),
+            genres: vec!["shounen".to_string(), "fantasy".to_string()],
+            description: Some("Rubber pirate boy adventures".to_string()),
+            url: "/manga/one-piece".to_string(),
             source: SOURCE_IDENTIFIER.to_string(),
+        }];
+        let image_quality = preferences
+            .text("image_quality")
+            .unwrap_or("high")
+            .to_owned();
+        Ok(Self {
+            manga,
+            image_quality,
+            session: Mutex::new(None),
+            cookies: OnceLock::new(),
         })
-        .collect()
+    }
+
+    fn set_cookie_jar(&self, jar: CookieJar) {
+        let _ = self.cookies.set(jar);
+    }
+
+    fn source_info(&self) -> Result<Source, SourceError> {
+        Ok(Source {
+            identifier: SOURCE_IDENTIFIER.to_owned(),
+            title: SOURCE_TITLE.to_owned(),
+            description: SOURCE_DESCRIPTION.to_owned(),
+            locale: Locale::ALL,
+            locales: vec![Locale::EN_US, Locale::PT_BR],
+        })
+    }
+
+    fn login(&self, credentials: Credentials) -> Result<(), SourceError> {
+        let username = match credentials {
+            Credentials::Password { username, password } if !password.is_empty() => username,
+            _ => return Err(SourceError::LoginRequired),
+        };
+        if let Some(cookies) = self.cookies.get() {
+            cookies.set_cookie(SOURCE_URL, &format!("session={}; Path=/", username));
+        }
+        *self.session.lock().unwrap() = Some(username);
+        Ok(())
+    }
+
+    fn logout(&self) -> Result<(), SourceError> {
+        if let Some(cookies) = self.cookies.get() {
+            cookies.set_cookie(SOURCE_URL, "session=; Path=/; Max-Age=0");
+        }
+        *self.session.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn manga_list(&self) -> Result<Vec<Manga>, SourceError> {
+        Ok(self.manga.clone())
+    }
+
+    fn search(&self, query: String) -> Result<Vec<Manga>, SourceError> {
+        let query = query.to_lowercase();
+        Ok(self
+            .manga
+            .iter()
+            .filter(|manga| manga.title.to_lowercase().contains(&query))
+            .cloned()
+            .collect())
+    }
+
+    fn chapter_list(
+        &self,
+        manga_identifier: String,
+        manga_url: String,
+        languages: Vec<Locale>,
+    ) -> Result<Vec<Chapter>, SourceError> {
+        // Only the first half is translated
+        let translations = [(Locale::EN_US, 100), (Locale::PT_BR, 50)];
+
+        let mut chapters = Vec::new();
+        for (language, size) in translations {
+            if languages.is_empty() || languages.iter().any(|l| language.matches(l)) {
+                chapters.extend(self.get_chapters(
+                    &manga_identifier,
+                    &manga_url,
+                    size,
+                    language,
+                )?);
+            }
+        }
+        Ok(chapters)
+    }
+
+    // Async functions are polled by the host's executor, so they can await I/O
+    async fn chapter_page_list(
+        &self,
+        chapter: f32,
+        chapter_url: String,
+        _manga: String,
+    ) -> Result<Vec<String>, SourceError> {
+        // The latest chapter is for logged in users only
+        if chapter == 100.0 && self.session.lock().unwrap().is_none() {
+            return Err(SourceError::LoginRequired);
+        }
+
+        let pages = (1..chapter as u32 % 20 + 2)
+            .map(|page| {
+                format!(
+                    "{}/pages/{}?quality={}",
+                    chapter_url, page, self.image_quality
+                )
+            })
+            .collect();
+        Ok(pages)
+    }
+}
+
+impl ExampleSource {
+    fn get_chapters(
+        &self,
+        identifier: &str,
+        url: &str,
+        size: u32,
+        language: Locale,
+    ) -> Result<Vec<Chapter>, SourceError> {
+        let manga = self
+            .manga
+            .iter()
+            .find(|m| m.identifier == identifier)
+            .ok_or(SourceError::NotFound)?;
+        let url = match language {
+            Locale::EN_US => url.to_owned(),
+            _ => format!("{}/{}", url, language.to_string().to_lowercase()),
+        };
+        let chapters = (1..size + 1)
+            .map(|chapter| Chapter {
+                chapter: chapter as f32,
+                title: format!("{} -- {}", &manga.title, chapter),
+                url: format!("{}/{}", url, chapter),
+                manga: identifier.to_string(),
+                source: SOURCE_IDENTIFIER.to_string(),
+                language,
+            })
+            .collect();
+        Ok(chapters)
+    }
 }